@@ -1,10 +1,707 @@
-mod spiders;
-
 use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use clap::{Parser, Subcommand, ValueEnum};
+use rust_demo::spiders::archive::{Archive, ArchiveQuery, ArchivedItem};
+use rust_demo::spiders::cache::{CacheFetcher, CacheMode, ResponseCache};
+use rust_demo::spiders::config::DriverConfig;
+use rust_demo::spiders::error::SpiderError;
+use rust_demo::spiders::export::{self, CsvRows, ExportFormat};
+use rust_demo::spiders::fetch::{self, Backend, Fetcher, WebDriverFetcher};
+use rust_demo::spiders::memo::{MemoEntry, Memoized, ResolutionMemo};
+use rust_demo::spiders::playlist::ToPlaylist;
+use rust_demo::spiders::poll::{self, ArchiveSink, FileSink, PollConfig, Poller, StdoutSink};
+use rust_demo::spiders::proxy::ProxyRotation;
+use rust_demo::spiders::rate::{HostLimit, RateLimiter};
+use rust_demo::spiders::retry::{RetryFetcher, RetryPolicy};
+use rust_demo::spiders::score::{self, BestMatch, MatchScorer};
+use rust_demo::spiders::session::Browser;
+use rust_demo::spiders::somafm::{self, PlaylistItem, ScrapeMode, Station, StationPlaylist};
+use rust_demo::spiders::youtube::{self, TupleHash, TupleVec, YPlaylistItem};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    println!("Hello, world!");
+/********************************************************************************************
+ * Exit codes
+********************************************************************************************/
+
+// 2 is what clap exits with on a usage error; a bad config file is much the same mistake
+const EXIT_CONFIG: u8 = 2;
+const EXIT_FETCH: u8 = 3;
+const EXIT_PARSE: u8 = 4;
+const EXIT_UNRESOLVED: u8 = 5;
+const EXIT_OUTPUT: u8 = 6;
+const EXIT_ARCHIVE: u8 = 7;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  2  invalid arguments or configuration
+  3  could not fetch a page (WebDriver or network)
+  4  could not parse a fetched page
+  5  a song could not be resolved to a video
+  6  could not write the output
+  7  could not read or write the archive, the page cache or the resolution memo";
+
+/********************************************************************************************
+ * Arguments
+********************************************************************************************/
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Scrape SomaFM song history and find the songs on YouTube"
+)]
+#[command(after_help = EXIT_CODES)]
+struct Cli {
+    /// WebDriver settings file (TOML); defaults to $RUST_DEMO_CONFIG.
+    /// RUST_DEMO_* environment variables override it, and the flags below override both.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// WebDriver (chromedriver / geckodriver) to drive the browser through
+    /// [default: http://localhost:8888]
+    #[arg(long, global = true)]
+    webdriver: Option<String>,
+
+    /// Browser to ask the WebDriver for: "chrome" or "firefox"
+    #[arg(long, global = true)]
+    browser: Option<Browser>,
+
+    /// Run the browser without a window
+    #[arg(long, global = true)]
+    headless: bool,
+
+    /// How to fetch SomaFM pages: "http" needs nothing running, "webdriver" drives a browser.
+    /// YouTube searches always go through the WebDriver.
+    #[arg(long, global = true, default_value = "http")]
+    backend: Backend,
+
+    /// SomaFM channel slug, as in https://somafm.com/<station>/songhistory.html.
+    /// Repeat or comma-separate to fetch several stations at once.
+    #[arg(
+        long,
+        short,
+        global = true,
+        value_delimiter = ',',
+        default_value = "groovesalad"
+    )]
+    station: Vec<Station>,
+
+    /// Skip song history rows that can't be read, and report them on stderr, instead of failing
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Seconds to wait between requests to a host (and its subdomains), e.g. somafm.com=5.
+    /// Repeat for several hosts. Waits get up to half as long again of random jitter.
+    #[arg(long, global = true, value_name = "HOST=SECONDS", value_parser = parse_host_secs)]
+    rate_limit: Vec<(String, u64)>,
+
+    /// Keep fetched pages in this directory, and reuse them while they are fresh
+    #[arg(long, global = true, value_name = "DIR")]
+    cache: Option<PathBuf>,
+
+    /// How long cached pages from a host (and its subdomains) stay fresh, e.g. somafm.com=300.
+    /// Defaults: somafm.com 60, youtube.com a week, anything else an hour.
+    #[arg(long, global = true, value_name = "HOST=SECONDS", value_parser = parse_host_secs)]
+    cache_ttl: Vec<(String, u64)>,
+
+    /// Only serve pages from the cache, however old, and fail on anything that isn't there
+    #[arg(long, global = true, requires = "cache")]
+    replay: bool,
+
+    /// Output format
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the channels SomaFM currently broadcasts
+    Channels,
+
+    /// Fetch and print the station's song history
+    Scrape {
+        /// Also add the songs to this archive file, skipping ones it already has
+        #[arg(long, short)]
+        archive: Option<PathBuf>,
+    },
+
+    /// Keep polling the song history and print new songs as they play, until Ctrl-C / SIGTERM
+    Poll {
+        /// Seconds between polls of each station
+        #[arg(long, short, default_value_t = 60)]
+        interval: u64,
+
+        /// Longest wait, in seconds, between retries of a station that keeps failing
+        #[arg(long, default_value_t = 900)]
+        max_backoff: u64,
+
+        /// Also add new songs to this archive file
+        #[arg(long, short)]
+        archive: Option<PathBuf>,
+
+        /// Also append new songs to this text file
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Search the songs kept in an archive file, for the selected stations
+    History {
+        /// Archive file written by `scrape --archive`
+        #[arg(long, short)]
+        archive: PathBuf,
+
+        /// Only songs by this artist (case-insensitive)
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Only songs played at or after this time (RFC 3339, e.g. 2026-10-18T14:00:00-07:00)
+        #[arg(long)]
+        since: Option<DateTime<FixedOffset>>,
+
+        /// Only songs played before this time (RFC 3339)
+        #[arg(long)]
+        until: Option<DateTime<FixedOffset>>,
+    },
+
+    /// Fetch the song history and look up a YouTube video for every song
+    Resolve {
+        /// Seconds to wait between YouTube searches
+        #[arg(long, short, default_value_t = 10)]
+        delay: u64,
+
+        /// Remember the video each song resolved to in this file, and only search for songs
+        /// it doesn't know yet
+        #[arg(long, short)]
+        memo: Option<PathBuf>,
+
+        /// Lowest match score, from 0 to 1, for a video to count as the song
+        #[arg(long, default_value_t = score::THRESHOLD, value_parser = parse_score)]
+        min_score: f64,
+    },
+
+    /// Resolve the song history and write it to a file
+    Export {
+        /// File to write to
+        #[arg(long, short)]
+        output: PathBuf,
+
+        /// Seconds to wait between YouTube searches
+        #[arg(long, short, default_value_t = 10)]
+        delay: u64,
+
+        /// Remember the video each song resolved to in this file, and only search for songs
+        /// it doesn't know yet
+        #[arg(long, short)]
+        memo: Option<PathBuf>,
+
+        /// Lowest match score, from 0 to 1, for a video to count as the song
+        #[arg(long, default_value_t = score::THRESHOLD, value_parser = parse_score)]
+        min_score: f64,
+    },
+
+    /// List or change the videos remembered by `resolve --memo`
+    Memo {
+        /// Memo file written by `resolve --memo`
+        #[arg(long, short)]
+        memo: PathBuf,
+
+        #[command(subcommand)]
+        action: Option<MemoAction>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MemoAction {
+    /// Print every remembered song (the default)
+    List,
+
+    /// Always resolve a song to this video, whatever a search finds
+    Pin {
+        #[arg(long)]
+        artist: String,
+
+        #[arg(long)]
+        song: String,
+
+        /// Video id or https://www.youtube.com/watch?v=... URL
+        #[arg(long, value_parser = parse_video)]
+        video: String,
+    },
+
+    /// Let a pinned song expire like a searched one
+    Unpin {
+        #[arg(long)]
+        artist: String,
+
+        #[arg(long)]
+        song: String,
+    },
+
+    /// Forget a song, pinned or not, so the next resolve searches for it again
+    Forget {
+        #[arg(long)]
+        artist: String,
+
+        #[arg(long)]
+        song: String,
+    },
+
+    /// Forget every song that isn't pinned and was resolved more than this many days ago
+    Expire {
+        #[arg(long)]
+        older_than: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// One line per song
+    Text,
+    /// Rust debug output
+    Debug,
+    /// One pretty-printed JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Extended M3U playlist
+    M3u8,
+    /// XSPF (XML) playlist
+    Xspf,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+struct Failure {
+    code: u8,
+    error: anyhow::Error,
+}
+
+trait OrExit<T> {
+    fn or_exit(self, code: u8) -> Result<T, Failure>;
+}
+
+impl<T> OrExit<T> for Result<T> {
+    fn or_exit(self, code: u8) -> Result<T, Failure> {
+        self.map_err(|error| Failure { code, error })
+    }
+}
+
+impl From<SpiderError> for Failure {
+    fn from(error: SpiderError) -> Self {
+        let code = match error {
+            SpiderError::Fetch { .. }
+            | SpiderError::Navigation { .. }
+            | SpiderError::CircuitOpen { .. }
+            | SpiderError::WaitTimeout { .. }
+            | SpiderError::NotCached { .. } => EXIT_FETCH,
+            SpiderError::MissingElement { .. }
+            | SpiderError::MalformedRow { .. }
+            | SpiderError::Parse { .. } => EXIT_PARSE,
+            SpiderError::Unresolved { .. } => EXIT_UNRESOLVED,
+            SpiderError::Export { .. } => EXIT_OUTPUT,
+            SpiderError::Archive { .. } | SpiderError::Cache { .. } | SpiderError::Memo { .. } => {
+                EXIT_ARCHIVE
+            }
+            SpiderError::Config { .. } => EXIT_CONFIG,
+        };
+
+        Failure {
+            code,
+            error: error.into(),
+        }
+    }
+}
+
+fn parse_host_secs(text: &str) -> Result<(String, u64), String> {
+    let (host, secs) = text
+        .split_once('=')
+        .ok_or_else(|| "expected HOST=SECONDS".to_string())?;
+    let secs = secs.parse().map_err(|e| format!("{}", e))?;
+
+    Ok((host.to_string(), secs))
+}
+
+fn parse_score(text: &str) -> Result<f64, String> {
+    let score = text.parse::<f64>().map_err(|e| format!("{}", e))?;
+
+    match (0.0..=1.0).contains(&score) {
+        true => Ok(score),
+        false => Err("expected a score from 0 to 1".to_string()),
+    }
+}
+
+fn parse_video(text: &str) -> Result<String, String> {
+    youtube::video_id(text).ok_or_else(|| "expected a video id or a watch URL".to_string())
+}
+
+fn host_limit(secs: u64) -> HostLimit {
+    let interval = Duration::from_secs(secs);
+
+    HostLimit::new(interval).with_jitter(interval / 2)
+}
+
+// The polite defaults, then `--delay`, then `--rate-limit`
+fn rate_limiter(cli: &Cli) -> RateLimiter {
+    let mut res = RateLimiter::polite();
+
+    if let Command::Resolve { delay, .. } | Command::Export { delay, .. } = cli.command {
+        res = res.with_host("youtube.com", host_limit(delay));
+    }
+
+    for (host, secs) in &cli.rate_limit {
+        res = res.with_host(host, host_limit(*secs));
+    }
+
+    res
+}
+
+fn scrape_mode(cli: &Cli) -> ScrapeMode {
+    match cli.lenient {
+        true => ScrapeMode::Lenient,
+        false => ScrapeMode::Strict,
+    }
+}
+
+// Config file and environment first, then the command line on top
+fn try_driver_config(cli: &Cli) -> Result<DriverConfig, Failure> {
+    let mut config = DriverConfig::try_load(cli.config.as_deref())?;
+
+    if let Some(webdriver) = &cli.webdriver {
+        config = config.with_endpoint(webdriver);
+    }
+
+    let mut capabilities = config.capabilities().clone();
+    if cli.browser.is_some() {
+        capabilities = capabilities.with_browser(cli.browser);
+    }
+    if cli.headless {
+        capabilities = capabilities.with_headless(true);
+    }
+
+    Ok(config.with_capabilities(capabilities))
+}
+
+// What every fetcher of one run shares: the driver settings, the proxies with their health, and
+// the page cache
+struct Transport {
+    driver: DriverConfig,
+    proxies: Arc<ProxyRotation>,
+    cache: Option<ResponseCache>,
+}
+
+fn response_cache(cli: &Cli) -> Option<ResponseCache> {
+    let mut res = ResponseCache::new(cli.cache.as_ref()?);
+
+    if cli.replay {
+        res = res.with_mode(CacheMode::Replay);
+    }
+    for (host, secs) in &cli.cache_ttl {
+        res = res.with_ttl(host, Duration::from_secs(*secs));
+    }
+
+    Some(res)
+}
+
+async fn try_transport(cli: &Cli) -> Result<Transport, Failure> {
+    let driver = try_driver_config(cli)?;
+    let proxies = Arc::new(ProxyRotation::new(driver.proxies().clone()));
+
+    for proxy in proxies.check_health().await {
+        eprintln!("Warning: proxy {} is unreachable, not using it", proxy);
+    }
+
+    Ok(Transport {
+        driver,
+        proxies,
+        cache: response_cache(cli),
+    })
+}
+
+impl Transport {
+    fn cached(&self, fetcher: Arc<dyn Fetcher>) -> Arc<dyn Fetcher> {
+        match &self.cache {
+            Some(cache) => Arc::new(CacheFetcher::new(fetcher, cache.clone())),
+            None => fetcher,
+        }
+    }
+}
+
+fn try_somafm_fetcher(cli: &Cli, transport: &Transport) -> Result<Arc<dyn Fetcher>, Failure> {
+    let res = fetch::new_fetcher(cli.backend, &transport.driver, transport.proxies.clone())?;
+
+    Ok(transport.cached(res))
+}
+
+// Closes the fetcher's browsers whether or not `res` is an error; that error comes first
+async fn try_closing<T>(fetcher: &dyn Fetcher, res: Result<T, SpiderError>) -> Result<T, Failure> {
+    let closed = fetcher.try_close().await;
+    let res = res?;
+    closed?;
+
+    Ok(res)
+}
+
+async fn try_scrape(cli: &Cli, transport: &Transport) -> Result<Vec<StationPlaylist>, Failure> {
+    let fetcher = try_somafm_fetcher(cli, transport)?;
+    let playlists =
+        somafm::try_fetch_playlists(fetcher.clone(), &cli.station, scrape_mode(cli)).await;
+    let playlists = try_closing(fetcher.as_ref(), playlists).await?;
+
+    for playlist in &playlists {
+        for diagnostic in playlist.diagnostics() {
+            eprintln!("Warning: {}: skipped {}", playlist.station(), diagnostic);
+        }
+    }
+
+    Ok(playlists)
+}
+
+async fn try_resolve(cli: &Cli) -> Result<Vec<YPlaylistItem>, Failure> {
+    let (memo, min_score) = match &cli.command {
+        Command::Resolve {
+            memo, min_score, ..
+        }
+        | Command::Export {
+            memo, min_score, ..
+        } => (memo.as_ref(), *min_score),
+        _ => (None, score::THRESHOLD),
+    };
+    let scorer = MatchScorer::new().with_threshold(min_score);
+
+    let mut memo = match memo {
+        Some(path) => ResolutionMemo::try_open(path)?,
+        None => ResolutionMemo::in_memory(),
+    };
+
+    let transport = try_transport(cli).await?;
+    let playlist = try_scrape(cli, &transport)
+        .await?
+        .into_iter()
+        .flat_map(StationPlaylist::into_playlist)
+        .collect::<Vec<PlaylistItem>>();
+
+    // Don't even start a browser when the memo knows every song
+    let unresolved = memo.unresolved(&playlist);
+    let results = match unresolved.is_empty() {
+        true => TupleHash::from(HashMap::new()),
+        false => {
+            let fetcher = transport.cached(Arc::new(RetryFetcher::new(
+                WebDriverFetcher::new(&transport.driver).with_proxies(transport.proxies.clone()),
+                RetryPolicy::default(),
+            )));
+
+            let results = youtube::try_search_playlist(fetcher.as_ref(), &unresolved).await;
+            try_closing(fetcher.as_ref(), results).await?
+        }
+    };
+    memo.try_record(&unresolved, &results, &scorer)?;
+
+    let resolved = TupleVec::<YPlaylistItem>::try_from((
+        playlist,
+        &Memoized::new(&memo, &BestMatch::new(&scorer, &results)),
+    ))?;
+
+    Ok(resolved.into_inner())
+}
+
+fn try_write<T: Debug + Display + Serialize + CsvRows + ToPlaylist>(
+    out: impl Write,
+    format: Format,
+    items: &[T],
+) -> Result<()> {
+    let mut out = BufWriter::new(out);
+
+    match format {
+        Format::Text => {
+            for item in items {
+                writeln!(out, "{}", item)?;
+            }
+        }
+        Format::Debug => writeln!(out, "{:#?}", items)?,
+        Format::Json => export::try_export(&mut out, ExportFormat::Json, items)?,
+        Format::Ndjson => export::try_export(&mut out, ExportFormat::Ndjson, items)?,
+        Format::Csv => export::try_export(&mut out, ExportFormat::Csv, items)?,
+        Format::M3u8 => export::try_export(&mut out, ExportFormat::M3u8, items)?,
+        Format::Xspf => export::try_export(&mut out, ExportFormat::Xspf, items)?,
+    }
+
+    out.flush()?;
 
     Ok(())
 }
+
+async fn run(cli: Cli) -> Result<(), Failure> {
+    RateLimiter::try_set_global(rate_limiter(&cli))?;
+
+    match cli.command {
+        Command::Channels => {
+            let transport = try_transport(&cli).await?;
+            let fetcher = try_somafm_fetcher(&cli, &transport)?;
+            let channels = somafm::try_fetch_channels(fetcher.as_ref()).await;
+            let channels = try_closing(fetcher.as_ref(), channels).await?;
+
+            try_write(io::stdout().lock(), cli.format, &channels).or_exit(EXIT_OUTPUT)
+        }
+        Command::Scrape { ref archive } => {
+            let transport = try_transport(&cli).await?;
+            let playlist = try_scrape(&cli, &transport).await?;
+
+            if let Some(path) = archive {
+                let added = Archive::try_open(path)?.try_merge_playlists(&playlist)?;
+                eprintln!("Archived {} new songs to {}", added, path.display());
+            }
+
+            try_write(io::stdout().lock(), cli.format, &playlist).or_exit(EXIT_OUTPUT)
+        }
+        Command::Poll {
+            interval,
+            max_backoff,
+            ref archive,
+            ref output,
+        } => {
+            let config = PollConfig {
+                interval: Duration::from_secs(interval),
+                max_backoff: Duration::from_secs(max_backoff),
+                mode: scrape_mode(&cli),
+            };
+
+            let transport = try_transport(&cli).await?;
+            // Runs for a long time, so give retired proxies a chance to come back
+            transport
+                .proxies
+                .spawn_health_checks(Duration::from_secs(5 * 60));
+
+            let fetcher = try_somafm_fetcher(&cli, &transport)?;
+            let mut poller =
+                Poller::new(fetcher.clone(), &cli.station, config).with_sink(StdoutSink);
+            if let Some(path) = archive {
+                poller = poller.with_sink(ArchiveSink::new(Archive::try_open(path)?));
+            }
+            if let Some(path) = output {
+                poller = poller.with_sink(FileSink::new(path));
+            }
+
+            // Every station's task has finished with its browser by the time this returns
+            let res = poller.try_run(poll::shutdown_signal()).await;
+            try_closing(fetcher.as_ref(), res).await
+        }
+        Command::History {
+            ref archive,
+            ref artist,
+            since,
+            until,
+        } => {
+            let archive = Archive::try_open(archive)?;
+
+            let mut query = ArchiveQuery::new();
+            if let Some(artist) = artist {
+                query = query.artist(artist);
+            }
+            if let Some(since) = since {
+                query = query.since(since);
+            }
+            if let Some(until) = until {
+                query = query.until(until);
+            }
+
+            let items = cli
+                .station
+                .iter()
+                .flat_map(|station| archive.query(&query.clone().station(station.clone())))
+                .cloned()
+                .collect::<Vec<ArchivedItem>>();
+
+            try_write(io::stdout().lock(), cli.format, &items).or_exit(EXIT_OUTPUT)
+        }
+        Command::Resolve { .. } => {
+            let resolved = try_resolve(&cli).await?;
+
+            try_write(io::stdout().lock(), cli.format, &resolved).or_exit(EXIT_OUTPUT)
+        }
+        Command::Export { ref output, .. } => {
+            let resolved = try_resolve(&cli).await?;
+
+            let file = File::create(output)
+                .map_err(anyhow::Error::from)
+                .or_exit(EXIT_OUTPUT)?;
+
+            try_write(file, cli.format, &resolved).or_exit(EXIT_OUTPUT)
+        }
+        Command::Memo {
+            ref memo,
+            ref action,
+        } => {
+            let mut memo = ResolutionMemo::try_open(memo)?;
+
+            match action.as_ref().unwrap_or(&MemoAction::List) {
+                MemoAction::List => {
+                    let entries = memo
+                        .entries()
+                        .into_iter()
+                        .cloned()
+                        .collect::<Vec<MemoEntry>>();
+
+                    return try_write(io::stdout().lock(), cli.format, &entries)
+                        .or_exit(EXIT_OUTPUT);
+                }
+                MemoAction::Pin {
+                    artist,
+                    song,
+                    video,
+                } => {
+                    memo.try_pin(artist, song, video)?;
+                    eprintln!("Pinned {} - {} to {}", artist, song, video);
+                }
+                MemoAction::Unpin { artist, song } => match memo.try_unpin(artist, song)? {
+                    true => eprintln!("Unpinned {} - {}", artist, song),
+                    false => eprintln!("No video remembered for {} - {}", artist, song),
+                },
+                MemoAction::Forget { artist, song } => match memo.try_forget(artist, song)? {
+                    true => eprintln!("Forgot {} - {}", artist, song),
+                    false => eprintln!("No video remembered for {} - {}", artist, song),
+                },
+                MemoAction::Expire { older_than } => {
+                    let before = i64::try_from(*older_than)
+                        .ok()
+                        .and_then(chrono::TimeDelta::try_days)
+                        .and_then(|age| chrono::Utc::now().checked_sub_signed(age))
+                        .ok_or_else(|| {
+                            SpiderError::config("--older-than", "more days than a date can go back")
+                        })?;
+                    let expired = memo.try_expire(before)?;
+                    eprintln!("Expired {} songs", expired);
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure { code, error }) => {
+            eprintln!("Error: {:#}", error);
+            ExitCode::from(code)
+        }
+    }
+}
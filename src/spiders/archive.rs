@@ -0,0 +1,377 @@
+/*
+    The song history page only shows the last ~20 songs, so keep every scrape in a local archive.
+
+    The archive is an append-only NDJSON file, one song per line, tagged with its station. A song is
+    the same song when station, played_at (as an absolute time), artist and song all match, so
+    merging overlapping scrapes only appends the rows we haven't seen yet. Rows keep the
+    "now playing" flag of the scrape that first saw them.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::somafm::{PlaylistItem, Station, StationPlaylist};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+// One line of the archive file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedItem {
+    station: Station,
+    #[serde(flatten)]
+    item: PlaylistItem,
+}
+
+type ArchiveKey = (Station, DateTime<Utc>, String, String);
+
+#[derive(Debug, Default)]
+pub struct Archive {
+    // None keeps everything in memory
+    path: Option<PathBuf>,
+    items: Vec<ArchivedItem>,
+    keys: HashSet<ArchiveKey>,
+}
+
+// Every filter is optional; `since` is inclusive, `until` exclusive
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveQuery {
+    station: Option<Station>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    artist: Option<String>,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl ArchivedItem {
+    pub fn station(&self) -> &Station {
+        &self.station
+    }
+
+    pub fn item(&self) -> &PlaylistItem {
+        &self.item
+    }
+
+    fn key(&self) -> ArchiveKey {
+        (
+            self.station.clone(),
+            self.item.played_at().with_timezone(&Utc),
+            self.item.artist().to_string(),
+            self.item.song().to_string(),
+        )
+    }
+}
+
+impl std::fmt::Display for ArchivedItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<16} {}", self.station, self.item)
+    }
+}
+
+impl Archive {
+    pub fn in_memory() -> Self {
+        Archive::default()
+    }
+
+    // Creates the file on the first merge if it doesn't exist yet
+    pub fn try_open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut res = Archive {
+            path: Some(path.to_path_buf()),
+            ..Archive::default()
+        };
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(res),
+            Err(e) => return Err(SpiderError::archive(path, e)),
+        };
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| SpiderError::archive(path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let item = serde_json::from_str::<ArchivedItem>(line.as_str()).map_err(|e| {
+                SpiderError::parse(format!("{} line {}", path.display(), index + 1), e)
+            })?;
+
+            // Tolerate a file that was appended to by hand or by an older version
+            if res.keys.insert(item.key()) {
+                res.items.push(item);
+            }
+        }
+
+        Ok(res)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[ArchivedItem] {
+        &self.items
+    }
+
+    // Returns how many songs were new
+    pub fn try_merge(&mut self, station: &Station, playlist: &[PlaylistItem]) -> Result<usize> {
+        // Keys of this batch, for a song that's in it twice
+        let mut batch = HashSet::new();
+        let added = playlist
+            .iter()
+            .map(|item| ArchivedItem {
+                station: station.clone(),
+                item: item.clone(),
+            })
+            .filter(|item| {
+                let key = item.key();
+                !self.keys.contains(&key) && batch.insert(key)
+            })
+            .collect::<Vec<_>>();
+
+        if added.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(path) = &self.path {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| SpiderError::archive(path, e))?;
+            let mut out = BufWriter::new(file);
+
+            for item in &added {
+                serde_json::to_writer(&mut out, item).map_err(|e| SpiderError::archive(path, e))?;
+                writeln!(out).map_err(|e| SpiderError::archive(path, e))?;
+            }
+
+            out.flush().map_err(|e| SpiderError::archive(path, e))?;
+        }
+
+        let res = added.len();
+        for item in added {
+            self.keys.insert(item.key());
+            self.items.push(item);
+        }
+
+        Ok(res)
+    }
+
+    pub fn try_merge_playlists(&mut self, playlists: &[StationPlaylist]) -> Result<usize> {
+        playlists.iter().try_fold(0, |added, playlist| {
+            Ok(added + self.try_merge(playlist.station(), playlist.playlist())?)
+        })
+    }
+
+    // Newest first, like the song history page
+    pub fn query(&self, query: &ArchiveQuery) -> Vec<&ArchivedItem> {
+        let mut res = self
+            .items
+            .iter()
+            .filter(|item| query.matches(item))
+            .collect::<Vec<_>>();
+
+        res.sort_by_key(|item| std::cmp::Reverse(item.item.played_at()));
+
+        res
+    }
+}
+
+impl ArchiveQuery {
+    pub fn new() -> Self {
+        ArchiveQuery::default()
+    }
+
+    pub fn station(mut self, station: Station) -> Self {
+        self.station = Some(station);
+        self
+    }
+
+    pub fn since<T: TimeZone>(mut self, since: DateTime<T>) -> Self {
+        self.since = Some(since.with_timezone(&Utc));
+        self
+    }
+
+    pub fn until<T: TimeZone>(mut self, until: DateTime<T>) -> Self {
+        self.until = Some(until.with_timezone(&Utc));
+        self
+    }
+
+    // Case-insensitive exact match
+    pub fn artist(mut self, artist: &str) -> Self {
+        self.artist = Some(artist.to_lowercase());
+        self
+    }
+
+    fn matches(&self, item: &ArchivedItem) -> bool {
+        let played_at = item.item.played_at();
+
+        self.station.as_ref().is_none_or(|s| s == &item.station)
+            && self.since.is_none_or(|since| played_at >= since)
+            && self.until.is_none_or(|until| played_at < until)
+            && self
+                .artist
+                .as_ref()
+                .is_none_or(|artist| artist == &item.item.artist().to_lowercase())
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::somafm::{test_played_at, test_song, SOMAFM_TZ};
+
+    #[test]
+    fn test_01_merge_dedup() {
+        let mut archive = Archive::in_memory();
+
+        let first = vec![
+            test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+            test_song(test_played_at(14, 16, 50), "Groove Matter", "Sun Dance"),
+        ];
+        // Next scrape overlaps by one song, and sees it at the same instant in UTC
+        let second = vec![
+            test_song(test_played_at(14, 26, 0), "Sofa Lofa", "Magic Shopkeeper"),
+            test_song(
+                test_played_at(14, 21, 19)
+                    .with_timezone(&Utc)
+                    .with_timezone(&SOMAFM_TZ),
+                "Afterlife",
+                "Sun Dance",
+            ),
+        ];
+
+        assert_eq!(
+            archive.try_merge(&Station::GROOVE_SALAD, &first).unwrap(),
+            2
+        );
+        assert_eq!(
+            archive.try_merge(&Station::GROOVE_SALAD, &second).unwrap(),
+            1
+        );
+        assert_eq!(
+            archive.try_merge(&Station::GROOVE_SALAD, &second).unwrap(),
+            0
+        );
+        // Same song on another station is another row
+        assert_eq!(archive.try_merge(&Station::LUSH, &first[..1]).unwrap(), 1);
+        assert_eq!(archive.len(), 4);
+
+        // Twice in one batch is still one row
+        let repeated = vec![test_song(test_played_at(15, 0, 0), "Sofa Lofa", "Otter Song"); 2];
+        assert_eq!(archive.try_merge(&Station::LUSH, &repeated).unwrap(), 1);
+        assert_eq!(archive.len(), 5);
+    }
+
+    #[test]
+    fn test_02_query() {
+        let mut archive = Archive::in_memory();
+        archive
+            .try_merge(
+                &Station::GROOVE_SALAD,
+                &[
+                    test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+                    test_song(test_played_at(14, 16, 50), "Groove Matter", "Sun Dance"),
+                    test_song(test_played_at(13, 1, 23), "Afterlife", "Dub"),
+                ],
+            )
+            .unwrap();
+        archive
+            .try_merge(
+                &Station::LUSH,
+                &[test_song(
+                    test_played_at(14, 0, 0),
+                    "afterlife",
+                    "Lush Life",
+                )],
+            )
+            .unwrap();
+
+        let res = archive.query(&ArchiveQuery::new().artist("AFTERLIFE"));
+        assert_eq!(
+            res.iter()
+                .map(|item| item.item().song())
+                .collect::<Vec<_>>(),
+            vec!["Sun Dance", "Lush Life", "Dub"]
+        );
+
+        let res = archive.query(
+            &ArchiveQuery::new()
+                .station(Station::GROOVE_SALAD)
+                .since(test_played_at(14, 0, 0))
+                .until(test_played_at(14, 21, 19)),
+        );
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].item().artist(), "Groove Matter");
+
+        assert_eq!(archive.query(&ArchiveQuery::new()).len(), 4);
+    }
+
+    #[test]
+    fn test_03_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-demo-archive-{}-test_03.ndjson",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut archive = Archive::try_open(&path).unwrap();
+        assert!(archive.is_empty());
+        archive
+            .try_merge(
+                &Station::DRONE_ZONE,
+                &[
+                    test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+                    test_song(test_played_at(14, 16, 50), "Groove Matter", "Sun Dance"),
+                ],
+            )
+            .unwrap();
+
+        let mut archive = Archive::try_open(&path).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive.items()[0].station(), &Station::DRONE_ZONE);
+        assert_eq!(
+            archive
+                .try_merge(
+                    &Station::DRONE_ZONE,
+                    &[test_song(
+                        test_played_at(14, 16, 50),
+                        "Groove Matter",
+                        "Sun Dance"
+                    )],
+                )
+                .unwrap(),
+            0
+        );
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.clone() + "{not json}\n").unwrap();
+        let res = Archive::try_open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text.lines().count(), 2);
+        assert!(matches!(res, Err(SpiderError::Parse { .. })));
+    }
+}
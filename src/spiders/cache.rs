@@ -0,0 +1,452 @@
+/*
+    Fetching the same song history or YouTube search over and over while developing is slow, and
+    not polite either, so `CacheFetcher` keeps every page it fetches on disk:
+
+        <dir>/<first two hex digits>/<sha256 of the request>.json
+
+    A request is the URL, plus the selector for element fetches. An entry stays fresh for its
+    host's TTL: the most specific domain configured ("youtube.com" covers "www.youtube.com"),
+    else the default. What happens on a lookup depends on the CacheMode:
+
+        ReadWrite   fresh entries come from disk; anything else is fetched and stored
+        Replay      everything comes from disk, however old, and a miss fails with `NotCached`.
+                    For running spiders and tests offline against recorded pages
+        Record      always fetches, and overwrites what's on disk
+
+    Entries that can't be read back are treated as missing, and ones that can't be written only
+    get a warning. `try_fetch_element_dated` answers with when the entry was stored, so a replayed
+    song history is dated from when it was fetched.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::fetch::{Fetched, Fetcher};
+use crate::spiders::rate;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    #[default]
+    ReadWrite,
+    Replay,
+    Record,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    mode: CacheMode,
+    default_ttl: Duration,
+    ttls: HashMap<String, Duration>,
+}
+
+// One file in the cache; url and css are kept to make the files easy to find and read
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    css: Option<String>,
+    // Seconds since the epoch
+    fetched_at: u64,
+    body: String,
+}
+
+pub struct CacheFetcher<F> {
+    inner: F,
+    cache: ResponseCache,
+}
+
+// Numbers the temp files, so writers of the same entry in this process don't share one
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+impl ResponseCache {
+    // The song history changes with every song, search results hardly ever
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        ResponseCache {
+            dir: dir.as_ref().to_path_buf(),
+            mode: CacheMode::default(),
+            default_ttl: Duration::from_secs(60 * 60),
+            ttls: HashMap::new(),
+        }
+        .with_ttl("somafm.com", Duration::from_secs(60))
+        .with_ttl("youtube.com", Duration::from_secs(7 * 24 * 60 * 60))
+    }
+
+    pub fn with_mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    // For `domain` and its subdomains
+    pub fn with_ttl(mut self, domain: &str, ttl: Duration) -> Self {
+        self.ttls.insert(domain.to_lowercase(), ttl);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    pub fn ttl(&self, host: &str) -> Duration {
+        rate::match_domain(&self.ttls, host)
+            .map(|(_, ttl)| *ttl)
+            .unwrap_or(self.default_ttl)
+    }
+
+    // Where the entry for this request lives, whether or not it exists
+    pub fn path(&self, url: &str, css: Option<&str>) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(url.as_bytes());
+        if let Some(css) = css {
+            hasher.update(b"\n");
+            hasher.update(css.as_bytes());
+        }
+
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        self.dir.join(&key[..2]).join(format!("{}.json", key))
+    }
+
+    fn load(&self, url: &str, css: Option<&str>) -> Option<CacheEntry> {
+        let text = std::fs::read_to_string(self.path(url, css)).ok()?;
+
+        serde_json::from_str::<CacheEntry>(text.as_str())
+            .ok()
+            // A hash collision would be a miracle, but cheap to rule out
+            .filter(|entry| entry.url == url && entry.css.as_deref() == css)
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        let age = Duration::from_secs(now().saturating_sub(entry.fetched_at));
+
+        age < self.ttl(rate::host(entry.url.as_str()).as_str())
+    }
+
+    fn try_store(&self, url: &str, css: Option<&str>, body: &str) -> Result<()> {
+        let path = self.path(url, css);
+        let entry = CacheEntry {
+            url: url.to_string(),
+            css: css.map(str::to_string),
+            fetched_at: now(),
+            body: body.to_string(),
+        };
+
+        let res = || -> std::io::Result<()> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+
+            // Write then rename, so a reader never sees half an entry. The temp file is this
+            // writer's own, or two processes storing the same entry would mix their writes
+            let tmp = path.with_extension(format!(
+                "json.{}-{}.tmp",
+                std::process::id(),
+                TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+            std::fs::rename(&tmp, &path)
+        };
+
+        res().map_err(|e| SpiderError::cache(&path, e))
+    }
+}
+
+impl CacheEntry {
+    fn into_fetched(self) -> Fetched {
+        let fetched_at = i64::try_from(self.fetched_at)
+            .ok()
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+            .unwrap_or_default();
+
+        Fetched {
+            body: self.body,
+            fetched_at,
+        }
+    }
+}
+
+impl<F: Fetcher> CacheFetcher<F> {
+    pub fn new(inner: F, cache: ResponseCache) -> Self {
+        CacheFetcher { inner, cache }
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+
+    async fn try_cached<Fut>(
+        &self,
+        url: &str,
+        css: Option<&str>,
+        fetch: impl FnOnce() -> Fut,
+    ) -> Result<Fetched>
+    where
+        Fut: Future<Output = Result<String>>,
+    {
+        let mode = self.cache.mode;
+
+        if mode != CacheMode::Record {
+            let entry = self
+                .cache
+                .load(url, css)
+                .filter(|entry| mode == CacheMode::Replay || self.cache.is_fresh(entry));
+
+            if let Some(entry) = entry {
+                return Ok(entry.into_fetched());
+            }
+        }
+
+        if mode == CacheMode::Replay {
+            return Err(SpiderError::not_cached(url));
+        }
+
+        let body = fetch().await?;
+        // The page is fetched either way; a cache that can't be written only costs a refetch
+        if let Err(e) = self.cache.try_store(url, css, body.as_str()) {
+            let source = std::error::Error::source(&e).map(|e| e.to_string());
+            eprintln!("Warning: {}: {}", e, source.unwrap_or_default());
+        }
+
+        Ok(Fetched {
+            body,
+            fetched_at: Utc::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl<F: Fetcher> Fetcher for CacheFetcher<F> {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        let res = self.try_fetch_element_dated(url, css).await?;
+
+        Ok(res.body)
+    }
+
+    async fn try_fetch_element_dated(&self, url: &str, css: &str) -> Result<Fetched> {
+        self.try_cached(url, Some(css), || self.inner.try_fetch_element(url, css))
+            .await
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        let res = self
+            .try_cached(url, None, || self.inner.try_fetch_text(url))
+            .await?;
+
+        Ok(res.body)
+    }
+
+    async fn try_close(&self) -> Result<()> {
+        self.inner.try_close().await
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::fetch::StubFetcher;
+    use crate::spiders::somafm::{self, Station};
+    use chrono::TimeZone;
+    use std::sync::Arc;
+
+    const SONG_HISTORY: &str = r#"
+<div id="playinc"><table><tr><td>14:21:19&nbsp; (Now)</td><td>Afterlife</td><td>Sun Dance</td></tr></table></div>
+"#;
+
+    // Heading, separator, then the song, as the scraper expects
+    const HISTORY_TABLE: &str = r#"
+<div id="playinc"><table><tbody>
+<tr><td>Played At</td><td>Artist</td><td>Song</td><td>Album</td><td></td></tr><tr><td colspan="5"></td></tr>
+<tr><td>14:21:19&nbsp; (Now)</td><td><a href="/buy">Afterlife</a></td><td>Sun Dance</td><td><a href="/buy">Speaking Tree</a></td><td></td></tr>
+</tbody></table></div>
+"#;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let res =
+            std::env::temp_dir().join(format!("rust-demo-cache-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&res);
+
+        res
+    }
+
+    #[test]
+    fn test_01_path_ttl() {
+        let cache = ResponseCache::new("/tmp/cache").with_ttl("m.youtube.com", Duration::ZERO);
+        let url = "https://somafm.com/groovesalad/songhistory.html";
+
+        let path = cache.path(url, None);
+        assert_eq!(path.extension().unwrap(), "json");
+        assert!(path.starts_with("/tmp/cache"));
+        assert_eq!(cache.path(url, None), path);
+        assert_ne!(cache.path(url, Some("#playinc")), path);
+
+        assert_eq!(cache.ttl("somafm.com"), Duration::from_secs(60));
+        assert_eq!(cache.ttl("m.youtube.com"), Duration::ZERO);
+        assert_eq!(cache.ttl("www.youtube.com"), Duration::from_secs(604800));
+        assert_eq!(cache.ttl("example.com"), Duration::from_secs(3600));
+    }
+
+    #[tokio::test]
+    async fn test_02_record_replay() {
+        let dir = cache_dir("test_02");
+        let station = Station::GROOVE_SALAD;
+        let url = station.song_history_url();
+
+        let online = StubFetcher::new().with_page(url.as_str(), SONG_HISTORY);
+        let fetcher = CacheFetcher::new(online, ResponseCache::new(&dir));
+        let recorded = somafm::try_get_playlist(&fetcher, &station).await.unwrap();
+
+        // No pages at all, so everything has to come from the cache
+        let offline = CacheFetcher::new(
+            StubFetcher::new(),
+            ResponseCache::new(&dir).with_mode(CacheMode::Replay),
+        );
+        let res = somafm::try_get_playlist(&offline, &station).await;
+        let missing = somafm::try_get_playlist(&offline, &Station::LUSH).await;
+        let text = offline.try_fetch_text(url.as_str()).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(res.unwrap(), recorded);
+        assert!(recorded.contains("Afterlife"));
+        assert!(matches!(missing, Err(SpiderError::NotCached { .. })));
+        // Element and whole-page fetches of one URL are separate entries
+        assert!(matches!(text, Err(SpiderError::NotCached { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_03_ttl() {
+        let dir = cache_dir("test_03");
+        let url = "https://somafm.com/channels.json";
+
+        let first = CacheFetcher::new(
+            StubFetcher::new().with_page(url, "[1]"),
+            ResponseCache::new(&dir),
+        );
+        assert_eq!(first.try_fetch_text(url).await.unwrap(), "[1]");
+
+        let changed = StubFetcher::new().with_page(url, "[2]");
+        let fresh = CacheFetcher::new(changed, ResponseCache::new(&dir));
+        let fresh = fresh.try_fetch_text(url).await.unwrap();
+
+        let changed = StubFetcher::new().with_page(url, "[2]");
+        let expired = CacheFetcher::new(
+            changed,
+            ResponseCache::new(&dir).with_ttl("somafm.com", Duration::ZERO),
+        );
+        let expired = expired.try_fetch_text(url).await.unwrap();
+
+        let changed = StubFetcher::new().with_page(url, "[3]");
+        let record = CacheFetcher::new(
+            changed,
+            ResponseCache::new(&dir).with_mode(CacheMode::Record),
+        );
+        let record = record.try_fetch_text(url).await.unwrap();
+
+        let replay = CacheFetcher::new(
+            StubFetcher::new(),
+            ResponseCache::new(&dir).with_mode(CacheMode::Replay),
+        );
+        let replay = replay.try_fetch_text(url).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fresh, "[1]");
+        // Refetched, and the new copy replaces the old
+        assert_eq!(expired, "[2]");
+        assert_eq!(record, "[3]");
+        assert_eq!(replay, "[3]");
+    }
+
+    #[tokio::test]
+    async fn test_04_replay_date() {
+        let dir = cache_dir("test_04");
+        let cache = ResponseCache::new(&dir).with_mode(CacheMode::Replay);
+        let station = Station::GROOVE_SALAD;
+        let url = station.song_history_url();
+
+        // Recorded long ago, in the afternoon
+        let fetched_at = somafm::SOMAFM_TZ
+            .with_ymd_and_hms(2025, 3, 1, 14, 30, 0)
+            .unwrap();
+        let path = cache.path(url.as_str(), Some("#playinc"));
+        let entry = CacheEntry {
+            url: url.clone(),
+            css: Some("#playinc".to_string()),
+            fetched_at: fetched_at.timestamp() as u64,
+            body: HISTORY_TABLE.to_string(),
+        };
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        let fetcher: Arc<dyn Fetcher> = Arc::new(CacheFetcher::new(StubFetcher::new(), cache));
+        let res =
+            somafm::try_fetch_playlists(fetcher, &[station], somafm::ScrapeMode::Strict).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Dated from the recording, not from today
+        let res = res.unwrap();
+        assert_eq!(
+            res[0].playlist()[0].played_at(),
+            somafm::SOMAFM_TZ
+                .with_ymd_and_hms(2025, 3, 1, 14, 21, 19)
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_05_unwritable() {
+        // A file where the cache directory should be, so nothing can be stored
+        let dir = cache_dir("test_05");
+        std::fs::write(&dir, "").unwrap();
+        let url = "https://somafm.com/channels.json";
+
+        let fetcher = CacheFetcher::new(
+            StubFetcher::new().with_page(url, "[1]"),
+            ResponseCache::new(&dir),
+        );
+        let res = fetcher.try_fetch_text(url).await;
+        std::fs::remove_file(&dir).unwrap();
+
+        assert_eq!(res.unwrap(), "[1]");
+    }
+}
@@ -0,0 +1,441 @@
+/*
+    Where the WebDriver is and how to start browsers on it. Later sources override earlier ones:
+
+        1. defaults         http://localhost:8888, rustls, one session, the driver's own browser
+        2. a TOML file      `--config <path>` or $RUST_DEMO_CONFIG
+        3. environment      RUST_DEMO_<KEY>, e.g. RUST_DEMO_HEADLESS=true
+        4. command line     whatever main() sets with the `with_` methods
+
+    Keys, in the file and (upper-cased, prefixed) in the environment:
+
+        webdriver = "http://localhost:4444"
+        tls = "native"                      # or "rustls"
+        sessions = 2                        # browsers kept open at once
+        browser = "firefox"                 # or "chrome"; unset uses whatever the driver runs
+        headless = true
+        user_agent = "Mozilla/5.0 ..."
+        window_size = "1280x800"
+        proxy = "socks5://127.0.0.1:9050"   # or http://host:port, or a list to take turns over;
+                                            # comma-separated in the environment
+        page_load_timeout = 30              # seconds
+
+        [host_proxies]                      # file only; hosts not listed use `proxy`
+        "youtube.com" = ["socks5://127.0.0.1:9050", "http://10.0.0.2:3128"]
+        "somafm.com" = []                   # direct
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::proxy::{self, ProxyRoutes};
+use crate::spiders::session::Capabilities;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+pub const DEFAULT_WEBDRIVER: &str = "http://localhost:8888";
+
+const ENV_PREFIX: &str = "RUST_DEMO_";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsBackend {
+    #[default]
+    Rustls,
+    Native,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriverConfig {
+    endpoint: String,
+    tls: TlsBackend,
+    sessions: usize,
+    capabilities: Capabilities,
+    proxies: ProxyRoutes,
+}
+
+// Every key is optional, so a file only needs the ones it changes. Values stay strings where
+// the environment needs the same parsing
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DriverFile {
+    webdriver: Option<String>,
+    tls: Option<String>,
+    sessions: Option<usize>,
+    browser: Option<String>,
+    headless: Option<bool>,
+    user_agent: Option<String>,
+    window_size: Option<String>,
+    proxy: Option<OneOrMany>,
+    page_load_timeout: Option<u64>,
+    host_proxies: Option<HashMap<String, Vec<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl std::fmt::Display for TlsBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsBackend::Rustls => f.write_str("rustls"),
+            TlsBackend::Native => f.write_str("native"),
+        }
+    }
+}
+
+impl FromStr for TlsBackend {
+    type Err = SpiderError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "rustls" => Ok(TlsBackend::Rustls),
+            "native" => Ok(TlsBackend::Native),
+            _ => Err(SpiderError::parse(
+                format!("TLS backend \"{}\"", name),
+                "expected \"rustls\" or \"native\"",
+            )),
+        }
+    }
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        DriverConfig {
+            endpoint: DEFAULT_WEBDRIVER.to_string(),
+            tls: TlsBackend::default(),
+            sessions: 1,
+            capabilities: Capabilities::new(),
+            proxies: ProxyRoutes::new(),
+        }
+    }
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(one) => vec![one],
+            OneOrMany::Many(many) => many,
+        }
+    }
+}
+
+fn try_parse_proxies(proxies: Vec<String>) -> Result<Vec<url::Url>> {
+    proxies
+        .iter()
+        .map(|proxy| proxy::try_parse_proxy(proxy.trim()))
+        .collect()
+}
+
+fn parse_window_size(text: &str) -> Result<(u32, u32)> {
+    text.split_once('x')
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+        .ok_or_else(|| {
+            SpiderError::parse(
+                format!("window size \"{}\"", text),
+                "expected <width>x<height>",
+            )
+        })
+}
+
+fn parse_bool(text: &str) -> Result<bool> {
+    match text.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" => Ok(false),
+        _ => Err(SpiderError::parse(
+            format!("\"{}\"", text),
+            "expected true or false",
+        )),
+    }
+}
+
+fn parse_number<T: FromStr>(text: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    text.trim()
+        .parse()
+        .map_err(|e| SpiderError::parse(format!("\"{}\"", text), e))
+}
+
+impl DriverConfig {
+    // Defaults, then the file (if any), then the environment
+    pub fn try_load(path: Option<&Path>) -> Result<Self> {
+        let from_env = std::env::var(format!("{}CONFIG", ENV_PREFIX)).ok();
+        let path = path.map(Path::to_path_buf).or(from_env.map(Into::into));
+
+        let res = match path {
+            Some(path) => DriverConfig::try_from_file(&path)?,
+            None => DriverConfig::default(),
+        };
+
+        res.try_merge_env(|key| std::env::var(key).ok())
+    }
+
+    pub fn try_from_file(path: &Path) -> Result<Self> {
+        let what = path.display().to_string();
+        let text = std::fs::read_to_string(path).map_err(|e| SpiderError::config(&what, e))?;
+
+        DriverConfig::default().try_merge_toml(&what, text.as_str())
+    }
+
+    pub fn try_merge_toml(self, what: &str, text: &str) -> Result<Self> {
+        let file = toml::from_str::<DriverFile>(text).map_err(|e| SpiderError::config(what, e))?;
+
+        self.try_merge(file)
+            .map_err(|e| SpiderError::config(what, e))
+    }
+
+    // `lookup` is `std::env::var` outside of tests
+    pub fn try_merge_env(self, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |key: &str| lookup(format!("{}{}", ENV_PREFIX, key).as_str());
+        let what = format!("{}* environment variables", ENV_PREFIX);
+
+        let res = || -> Result<DriverFile> {
+            Ok(DriverFile {
+                webdriver: var("WEBDRIVER"),
+                tls: var("TLS"),
+                sessions: var("SESSIONS").as_deref().map(parse_number).transpose()?,
+                browser: var("BROWSER"),
+                headless: var("HEADLESS").as_deref().map(parse_bool).transpose()?,
+                user_agent: var("USER_AGENT"),
+                window_size: var("WINDOW_SIZE"),
+                proxy: var("PROXY")
+                    .map(|proxies| OneOrMany::Many(proxies.split(',').map(Into::into).collect())),
+                page_load_timeout: var("PAGE_LOAD_TIMEOUT")
+                    .as_deref()
+                    .map(parse_number)
+                    .transpose()?,
+                host_proxies: None,
+            })
+        };
+
+        let file = res().map_err(|e| SpiderError::config(&what, e))?;
+
+        self.try_merge(file)
+            .map_err(|e| SpiderError::config(&what, e))
+    }
+
+    fn try_merge(mut self, file: DriverFile) -> Result<Self> {
+        let mut capabilities = self.capabilities;
+
+        if let Some(webdriver) = file.webdriver {
+            self.endpoint = webdriver;
+        }
+        if let Some(tls) = file.tls {
+            self.tls = tls.parse()?;
+        }
+        if let Some(sessions) = file.sessions {
+            self.sessions = sessions.max(1);
+        }
+        if let Some(browser) = file.browser {
+            capabilities = capabilities.with_browser(Some(browser.parse()?));
+        }
+        if let Some(headless) = file.headless {
+            capabilities = capabilities.with_headless(headless);
+        }
+        if let Some(user_agent) = file.user_agent {
+            capabilities = capabilities.with_user_agent(user_agent.as_str());
+        }
+        if let Some(window_size) = file.window_size {
+            let (width, height) = parse_window_size(window_size.as_str())?;
+            capabilities = capabilities.with_window_size(width, height);
+        }
+        if let Some(proxy) = file.proxy {
+            let proxies = try_parse_proxies(proxy.into_vec())?;
+            self.proxies = self.proxies.with_default(proxies);
+        }
+        for (domain, proxies) in file.host_proxies.unwrap_or_default() {
+            let proxies = try_parse_proxies(proxies)?;
+            self.proxies = self.proxies.with_host(domain.as_str(), proxies);
+        }
+        if let Some(timeout) = file.page_load_timeout {
+            capabilities = capabilities.with_page_load_timeout(Some(Duration::from_secs(timeout)));
+        }
+
+        self.capabilities = capabilities;
+
+        Ok(self)
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsBackend) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_sessions(mut self, sessions: usize) -> Self {
+        self.sessions = sessions.max(1);
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_proxies(mut self, proxies: ProxyRoutes) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn tls(&self) -> TlsBackend {
+        self.tls
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.sessions
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn proxies(&self) -> &ProxyRoutes {
+        &self.proxies
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::session::Browser;
+    use std::collections::HashMap;
+
+    const DRIVER_TOML: &str = r#"
+webdriver = "http://localhost:4444"
+tls = "native"
+sessions = 2
+browser = "firefox"
+headless = true
+window_size = "1280x800"
+page_load_timeout = 30
+
+[host_proxies]
+"youtube.com" = ["socks5://127.0.0.1:9050", "http://10.0.0.2:3128"]
+"somafm.com" = []
+"#;
+
+    #[test]
+    fn test_01_defaults() {
+        let res = DriverConfig::default();
+
+        assert_eq!(res.endpoint(), "http://localhost:8888");
+        assert_eq!(res.tls(), TlsBackend::Rustls);
+        assert_eq!(res.sessions(), 1);
+        assert_eq!(res.capabilities(), &Capabilities::new());
+        assert!(res.proxies().is_empty());
+    }
+
+    #[test]
+    fn test_02_toml() {
+        let res = DriverConfig::default()
+            .try_merge_toml("driver.toml", DRIVER_TOML)
+            .unwrap();
+
+        assert_eq!(res.endpoint(), "http://localhost:4444");
+        assert_eq!(res.tls(), TlsBackend::Native);
+        assert_eq!(res.sessions(), 2);
+        assert_eq!(res.capabilities().browser(), Some(Browser::Firefox));
+        assert!(res.capabilities().headless());
+        assert_eq!(res.capabilities().window_size(), Some((1280, 800)));
+        assert_eq!(
+            res.capabilities().page_load_timeout(),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(res.proxies().route("www.youtube.com").1.len(), 2);
+        assert!(res.proxies().route("somafm.com").1.is_empty());
+        // Not in the file, so still the default
+        assert_eq!(res.capabilities().user_agent(), None);
+        assert!(res.proxies().route("example.com").1.is_empty());
+
+        assert!(matches!(
+            DriverConfig::default().try_merge_toml("driver.toml", "headles = true"),
+            Err(SpiderError::Config { .. })
+        ));
+        assert!(DriverConfig::default()
+            .try_merge_toml("driver.toml", r#"browser = "safari""#)
+            .is_err());
+    }
+
+    #[test]
+    fn test_03_env_overrides_file() {
+        let env = HashMap::from([
+            ("RUST_DEMO_WEBDRIVER", "http://selenium:4444"),
+            ("RUST_DEMO_HEADLESS", "0"),
+            (
+                "RUST_DEMO_PROXY",
+                "http://proxy.example.com:3128, socks5://127.0.0.1:9050",
+            ),
+            ("RUST_DEMO_USER_AGENT", "rust-demo/0.1"),
+        ]);
+
+        let res = DriverConfig::default()
+            .try_merge_toml("driver.toml", DRIVER_TOML)
+            .unwrap()
+            .try_merge_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+
+        assert_eq!(res.endpoint(), "http://selenium:4444");
+        assert!(!res.capabilities().headless());
+        assert_eq!(
+            res.proxies()
+                .route("example.com")
+                .1
+                .iter()
+                .map(url::Url::as_str)
+                .collect::<Vec<_>>(),
+            vec!["http://proxy.example.com:3128/", "socks5://127.0.0.1:9050"]
+        );
+        // Hosts with a route of their own keep it
+        assert_eq!(res.proxies().route("www.youtube.com").1.len(), 2);
+        assert_eq!(res.capabilities().user_agent(), Some("rust-demo/0.1"));
+        // From the file
+        assert_eq!(res.tls(), TlsBackend::Native);
+
+        let res = DriverConfig::default().try_merge_env(|key| {
+            (key == "RUST_DEMO_PAGE_LOAD_TIMEOUT").then(|| "soon".to_string())
+        });
+        assert!(matches!(res, Err(SpiderError::Config { .. })));
+    }
+
+    #[test]
+    fn test_04_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-demo-config-{}-test_04.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, DRIVER_TOML).unwrap();
+
+        let res = DriverConfig::try_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.unwrap().sessions(), 2);
+        assert!(matches!(
+            DriverConfig::try_from_file(Path::new("/does/not/exist.toml")),
+            Err(SpiderError::Config { .. })
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+/*
+    One error type for every spider, so callers can decide what to do from the variant:
+
+        Fetch / Navigation      the site or the WebDriver didn't answer -- worth retrying later,
+                                unless the site said no (4xx)
+        CircuitOpen             a host failed so often that we stopped asking it for a while
+        MissingElement          the page loaded but doesn't look like we expect -- alert
+        WaitTimeout             an element still wasn't rendered when the browser gave up
+                                waiting -- worth retrying, the page may just have been slow
+        MalformedRow            one row of a table couldn't be read; carries the raw HTML
+        Parse                   a value on the page (time, number, JSON, ...) didn't parse
+        Unresolved              no YouTube video for a song
+        Export                  scraped data couldn't be written out
+        Archive                 the local song archive couldn't be read or written
+        Cache / NotCached       the page cache couldn't be read or written, or had no copy of
+                                a page to replay
+        Memo                    the remembered song -> video resolutions couldn't be read or
+                                written
+        Config                  the driver config file or environment has a bad value
+*/
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+pub type Result<T, E = SpiderError> = std::result::Result<T, E>;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpiderError {
+    #[error("failed to fetch {url}")]
+    Fetch {
+        url: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("failed to navigate to {url}")]
+    Navigation {
+        url: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("{host} keeps failing, not trying it again for {}s", retry_in.as_secs())]
+    CircuitOpen {
+        host: String,
+        retry_in: std::time::Duration,
+    },
+
+    #[error("no element matching \"{selector}\" in {page}")]
+    MissingElement { page: String, selector: String },
+
+    #[error("timed out waiting for \"{selector}\" in {page}")]
+    WaitTimeout { page: String, selector: String },
+
+    #[error("malformed row {index}: {reason}")]
+    MalformedRow {
+        index: usize,
+        reason: String,
+        html: String,
+    },
+
+    #[error("failed to parse {what}: {reason}")]
+    Parse { what: String, reason: String },
+
+    #[error("no video found for \"{query}\"")]
+    Unresolved { query: String },
+
+    #[error("failed to write {format}")]
+    Export {
+        format: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("failed to access archive {path}")]
+    Archive {
+        path: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("failed to access cache {path}")]
+    Cache {
+        path: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("failed to access resolution memo {path}")]
+    Memo {
+        path: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("no cached copy of {url} to replay")]
+    NotCached { url: String },
+
+    #[error("invalid configuration in {what}")]
+    Config {
+        what: String,
+        #[source]
+        source: BoxError,
+    },
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl SpiderError {
+    pub fn fetch(url: &str, source: impl Into<BoxError>) -> Self {
+        SpiderError::Fetch {
+            url: url.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn navigation(url: &str, source: impl Into<BoxError>) -> Self {
+        SpiderError::Navigation {
+            url: url.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn circuit_open(host: &str, retry_in: std::time::Duration) -> Self {
+        SpiderError::CircuitOpen {
+            host: host.to_string(),
+            retry_in,
+        }
+    }
+
+    pub fn missing_element(page: &str, selector: &str) -> Self {
+        SpiderError::MissingElement {
+            page: page.to_string(),
+            selector: selector.to_string(),
+        }
+    }
+
+    pub fn wait_timeout(page: &str, selector: &str) -> Self {
+        SpiderError::WaitTimeout {
+            page: page.to_string(),
+            selector: selector.to_string(),
+        }
+    }
+
+    pub fn malformed_row(index: usize, reason: impl ToString, html: &str) -> Self {
+        SpiderError::MalformedRow {
+            index,
+            reason: reason.to_string(),
+            html: html.to_string(),
+        }
+    }
+
+    pub fn export(format: impl ToString, source: impl Into<BoxError>) -> Self {
+        SpiderError::Export {
+            format: format.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn archive(path: &std::path::Path, source: impl Into<BoxError>) -> Self {
+        SpiderError::Archive {
+            path: path.display().to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn cache(path: &std::path::Path, source: impl Into<BoxError>) -> Self {
+        SpiderError::Cache {
+            path: path.display().to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn memo(path: &std::path::Path, source: impl Into<BoxError>) -> Self {
+        SpiderError::Memo {
+            path: path.display().to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn not_cached(url: &str) -> Self {
+        SpiderError::NotCached {
+            url: url.to_string(),
+        }
+    }
+
+    pub fn config(what: &str, source: impl Into<BoxError>) -> Self {
+        SpiderError::Config {
+            what: what.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn parse(what: impl ToString, reason: impl ToString) -> Self {
+        SpiderError::Parse {
+            what: what.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    // Timeouts, refused connections, 5xx, stale elements... Anything else, like a selector that
+    // isn't on a page that did load, would fail the same way next time
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SpiderError::Fetch { source, .. } | SpiderError::Navigation { source, .. } => {
+                !is_refused(source.as_ref())
+            }
+            SpiderError::WaitTimeout { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+// The server answered and said no. 408 and 429 only mean "not now"
+fn is_refused(e: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+        .is_some_and(|status| {
+            status.is_client_error()
+                && status != reqwest::StatusCode::REQUEST_TIMEOUT
+                && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+// Selectors are all literals, so a bad one is a bug rather than bad input
+pub(crate) fn selector(css: &str) -> Result<scraper::Selector> {
+    scraper::Selector::parse(css)
+        .map_err(|e| SpiderError::parse(format!("selector \"{}\"", css), format!("{:?}", e)))
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_01_display() {
+        let res = SpiderError::malformed_row(3, "no artist link", "<tr><td>14:21:19</td></tr>");
+
+        assert_eq!(res.to_string(), "malformed row 3: no artist link");
+        assert!(matches!(
+            res,
+            SpiderError::MalformedRow { index: 3, ref html, .. } if html == "<tr><td>14:21:19</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn test_02_source() {
+        use std::error::Error;
+
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        let res = SpiderError::fetch("https://somafm.com/", io);
+
+        assert_eq!(res.to_string(), "failed to fetch https://somafm.com/");
+        assert_eq!(res.source().unwrap().to_string(), "timed out");
+    }
+
+    #[test]
+    fn test_03_selector() {
+        assert!(selector("table").is_ok());
+        assert!(matches!(selector("<<"), Err(SpiderError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_04_retryable() {
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+
+        assert!(SpiderError::fetch("https://somafm.com/", io).is_retryable());
+        assert!(
+            SpiderError::navigation("https://www.youtube.com/", "stale element").is_retryable()
+        );
+        assert!(!SpiderError::missing_element("https://somafm.com/", "#playinc").is_retryable());
+        assert!(SpiderError::wait_timeout("https://www.youtube.com/", "#contents").is_retryable());
+        assert!(!SpiderError::parse("played_at", "not a time").is_retryable());
+        assert!(
+            !SpiderError::circuit_open("somafm.com", std::time::Duration::from_secs(1))
+                .is_retryable()
+        );
+    }
+}
@@ -0,0 +1,391 @@
+/*
+    Writes scraped playlists to files for downstream jobs:
+
+        Json      one pretty-printed array
+        Ndjson    one compact JSON object per line, so files can be appended to and streamed
+        Csv       a header row, then one row per song, columns in the order of `CSV_HEADER`
+        M3u8      playlist files for media players, see `playlist`
+        Xspf
+
+    Timestamps are RFC 3339 with their UTC offset in every format.
+*/
+
+use crate::spiders::archive::ArchivedItem;
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::memo::MemoEntry;
+use crate::spiders::playlist::{self, ToPlaylist};
+use crate::spiders::somafm::{Channel, PlaylistItem, StationPlaylist};
+use crate::spiders::youtube::YPlaylistItem;
+use serde::Serialize;
+use std::io::Write;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Ndjson,
+    Csv,
+    M3u8,
+    Xspf,
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Json => f.write_str("json"),
+            ExportFormat::Ndjson => f.write_str("ndjson"),
+            ExportFormat::Csv => f.write_str("csv"),
+            ExportFormat::M3u8 => f.write_str("m3u8"),
+            ExportFormat::Xspf => f.write_str("xspf"),
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = SpiderError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "m3u8" => Ok(ExportFormat::M3u8),
+            "xspf" => Ok(ExportFormat::Xspf),
+            _ => Err(SpiderError::parse(
+                format!("export format \"{}\"", name),
+                "expected \"json\", \"ndjson\", \"csv\", \"m3u8\" or \"xspf\"",
+            )),
+        }
+    }
+}
+
+// Flattens a value into CSV rows. Columns are fixed per type; only ever append new ones
+pub trait CsvRows {
+    const CSV_HEADER: &'static [&'static str];
+
+    fn csv_rows(&self) -> Vec<Vec<String>>;
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+fn playlist_item_row(item: &PlaylistItem) -> Vec<String> {
+    vec![
+        item.played_at().to_rfc3339(),
+        item.is_now_playing().to_string(),
+        item.artist().to_string(),
+        item.song().to_string(),
+        item.album().to_string(),
+        item.artist_link()
+            .map(|link| link.url().to_string())
+            .unwrap_or_default(),
+        item.album_link()
+            .map(|link| link.url().to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+impl CsvRows for PlaylistItem {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "played_at",
+        "now_playing",
+        "artist",
+        "song",
+        "album",
+        "artist_url",
+        "album_url",
+    ];
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        vec![playlist_item_row(self)]
+    }
+}
+
+impl CsvRows for YPlaylistItem {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "played_at",
+        "now_playing",
+        "artist",
+        "song",
+        "album",
+        "artist_url",
+        "album_url",
+        "video",
+    ];
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let mut row = playlist_item_row(self.playlist_item());
+        row.push(self.video().to_string());
+
+        vec![row]
+    }
+}
+
+// One row per song, with the station in front
+impl CsvRows for StationPlaylist {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "station",
+        "played_at",
+        "now_playing",
+        "artist",
+        "song",
+        "album",
+        "artist_url",
+        "album_url",
+    ];
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        self.playlist()
+            .iter()
+            .map(|item| {
+                let mut row = vec![self.station().to_string()];
+                row.extend(playlist_item_row(item));
+                row
+            })
+            .collect()
+    }
+}
+
+impl CsvRows for ArchivedItem {
+    const CSV_HEADER: &'static [&'static str] = StationPlaylist::CSV_HEADER;
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let mut row = vec![self.station().to_string()];
+        row.extend(playlist_item_row(self.item()));
+
+        vec![row]
+    }
+}
+
+impl CsvRows for Channel {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "station",
+        "title",
+        "genres",
+        "dj",
+        "listeners",
+        "description",
+    ];
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.station().to_string(),
+            self.title().to_string(),
+            self.genres().join("|"),
+            self.dj().to_string(),
+            self.listeners().to_string(),
+            self.description().to_string(),
+        ]]
+    }
+}
+
+impl CsvRows for MemoEntry {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "artist",
+        "song",
+        "video",
+        "resolved_at",
+        "confidence",
+        "pinned",
+    ];
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.artist().to_string(),
+            self.song().to_string(),
+            self.video_url(),
+            self.resolved_at().to_rfc3339(),
+            self.confidence()
+                .map(|confidence| confidence.to_string())
+                .unwrap_or_default(),
+            self.is_pinned().to_string(),
+        ]]
+    }
+}
+
+pub fn try_write_json<T: Serialize>(mut out: impl Write, items: &[T]) -> Result<()> {
+    let format = ExportFormat::Json;
+
+    serde_json::to_writer_pretty(&mut out, items).map_err(|e| SpiderError::export(format, e))?;
+    writeln!(out).map_err(|e| SpiderError::export(format, e))?;
+
+    Ok(())
+}
+
+pub fn try_write_ndjson<T: Serialize>(mut out: impl Write, items: &[T]) -> Result<()> {
+    let format = ExportFormat::Ndjson;
+
+    for item in items {
+        serde_json::to_writer(&mut out, item).map_err(|e| SpiderError::export(format, e))?;
+        writeln!(out).map_err(|e| SpiderError::export(format, e))?;
+    }
+
+    Ok(())
+}
+
+pub fn try_write_csv<T: CsvRows>(out: impl Write, items: &[T]) -> Result<()> {
+    let format = ExportFormat::Csv;
+    let mut writer = csv::Writer::from_writer(out);
+
+    writer
+        .write_record(T::CSV_HEADER)
+        .map_err(|e| SpiderError::export(format, e))?;
+
+    for row in items.iter().flat_map(CsvRows::csv_rows) {
+        writer
+            .write_record(&row)
+            .map_err(|e| SpiderError::export(format, e))?;
+    }
+
+    writer.flush().map_err(|e| SpiderError::export(format, e))?;
+
+    Ok(())
+}
+
+pub fn try_export<T: Serialize + CsvRows + ToPlaylist>(
+    out: impl Write,
+    format: ExportFormat,
+    items: &[T],
+) -> Result<()> {
+    match format {
+        ExportFormat::Json => try_write_json(out, items),
+        ExportFormat::Ndjson => try_write_ndjson(out, items),
+        ExportFormat::Csv => try_write_csv(out, items),
+        ExportFormat::M3u8 => playlist::try_write_m3u8(out, &T::playlist_entries(items)),
+        ExportFormat::Xspf => playlist::try_write_xspf(out, None, &T::playlist_entries(items)),
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::somafm::{self, Station, SOMAFM_TZ};
+    use crate::spiders::youtube::TupleVec;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    const HISTORY_HTML: &str = r#"
+<table>
+<tbody>
+<tr><th>Played At</th><th>Artist</th><th>Song</th><th>Album</th><th></th></tr>
+<tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+<tr><td>14:21:19&nbsp; (Now) </td>
+<td><a target="_blank" href="https://www.discogs.com/artist/Afterlife">Afterlife</a></td><td>Dub, Strings and a Drum, Part "1"</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Simply">Simply</a></td>
+<td></td>
+</tr>
+<tr><td>14:16:50</td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&artist=Groove%20Matter">Groove Matter</a></td><td>Sun Dance</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Groovology">Groovology</a></td>
+<td></td>
+</tr>
+<tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+</tbody>
+</table>
+"#;
+
+    fn playlist() -> Vec<PlaylistItem> {
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 14, 25, 0).unwrap();
+
+        somafm::try_scrape_html_at(HISTORY_HTML, reference).unwrap()
+    }
+
+    #[test]
+    fn test_01_parse_format() {
+        assert_eq!(
+            "ndjson".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Ndjson
+        );
+        assert_eq!(ExportFormat::Csv.to_string(), "csv");
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_02_json_round_trip() {
+        let playlist = playlist();
+        let mut out = Vec::new();
+
+        try_write_json(&mut out, &playlist).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        println!("JSON: {}", text);
+
+        assert!(text.contains(r#""played_at": "2026-10-18T14:21:19-07:00""#));
+        assert!(text.contains(r#""kind": "discogs""#));
+        assert_eq!(
+            serde_json::from_str::<Vec<PlaylistItem>>(text.as_str()).unwrap(),
+            playlist
+        );
+    }
+
+    #[test]
+    fn test_03_ndjson_round_trip() {
+        let playlist = playlist();
+        let resolved = TupleVec::<YPlaylistItem>::try_from((
+            playlist,
+            &HashMap::from([
+                (
+                    "Afterlife + Dub, Strings and a Drum, Part \"1\"".to_string(),
+                    "https://www.youtube.com/watch?v=Qp6pS3cvCKk".to_string(),
+                ),
+                (
+                    "Groove Matter + Sun Dance".to_string(),
+                    "https://www.youtube.com/watch?v=8KsC5Cak3dE".to_string(),
+                ),
+            ]),
+        ))
+        .unwrap()
+        .into_inner();
+        let mut out = Vec::new();
+
+        try_write_ndjson(&mut out, &resolved).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        // Flattened, so the song and its video sit side by side
+        assert!(lines[1].starts_with(r#"{"played_at":"2026-10-18T14:16:50-07:00""#));
+        assert!(lines[1].ends_with(r#""video":"https://www.youtube.com/watch?v=8KsC5Cak3dE"}"#));
+        assert_eq!(
+            lines
+                .iter()
+                .map(|line| serde_json::from_str::<YPlaylistItem>(line).unwrap())
+                .collect::<Vec<_>>(),
+            resolved
+        );
+    }
+
+    #[test]
+    fn test_04_csv() {
+        let report = somafm::try_scrape_station(
+            Station::GROOVE_SALAD,
+            HISTORY_HTML,
+            somafm::ScrapeMode::Strict,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+
+        try_export(&mut out, ExportFormat::Csv, &[report]).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        println!("CSV: {}", text);
+
+        assert_eq!(
+            lines[0],
+            "station,played_at,now_playing,artist,song,album,artist_url,album_url"
+        );
+        assert_eq!(lines.len(), 3);
+        // Commas and quotes in a title are quoted, not split
+        assert!(lines[1].contains(r#","Dub, Strings and a Drum, Part ""1""","#));
+        assert!(lines[2].starts_with("groovesalad,"));
+        assert!(lines[2].contains(",false,Groove Matter,Sun Dance,Groovology,"));
+    }
+}
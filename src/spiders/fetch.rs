@@ -0,0 +1,534 @@
+/*
+    Every spider gets its pages through a `Fetcher`, so the transport can be picked at runtime:
+
+        HttpFetcher       plain HTTP GET, no browser needed. Good for static pages like the
+                          somafm song history and channels.json
+        WebDriverFetcher  drives chromedriver / geckodriver through fantoccini. Needed for pages
+                          rendered client side, like the YouTube search results. Borrows its
+                          browser sessions from a `SessionPool`
+        StubFetcher       serves canned pages from memory or from fixture files, for tests. See
+                          also `CacheFetcher` in replay mode, for pages recorded earlier
+
+    HttpFetcher and WebDriverFetcher wait on a `RateLimiter` before every request, and route it
+    through a proxy if a `ProxyRotation` has one for the host. `new_fetcher` also wraps them in a
+    `RetryFetcher`, see spiders::retry
+*/
+
+use crate::spiders::config::DriverConfig;
+use crate::spiders::error::{selector, Result, SpiderError};
+use crate::spiders::proxy::ProxyRotation;
+use crate::spiders::rate::RateLimiter;
+use crate::spiders::retry::{RetryFetcher, RetryPolicy};
+use crate::spiders::session::SessionPool;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use fantoccini::error::CmdError;
+use fantoccini::Locator;
+use scraper::Html;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    // Outer HTML of the first element matching `css` on the page at `url`
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String>;
+
+    // `try_fetch_element`, and when the page was fetched: now, unless it came from a cache
+    async fn try_fetch_element_dated(&self, url: &str, css: &str) -> Result<Fetched> {
+        let body = self.try_fetch_element(url, css).await?;
+
+        Ok(Fetched {
+            body,
+            fetched_at: Utc::now(),
+        })
+    }
+
+    // Raw body of `url`, for JSON and other non-HTML resources
+    async fn try_fetch_text(&self, url: &str) -> Result<String>;
+
+    // Closes whatever stays open between fetches, like browser sessions; call when done
+    async fn try_close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fetched {
+    pub body: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Http,
+    WebDriver,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = SpiderError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "http" => Ok(Backend::Http),
+            "webdriver" => Ok(Backend::WebDriver),
+            _ => Err(SpiderError::parse(
+                format!("backend \"{}\"", name),
+                "expected \"http\" or \"webdriver\"",
+            )),
+        }
+    }
+}
+
+// A client per proxy, each made on first use; `None` connects directly
+pub struct HttpFetcher {
+    clients: Mutex<HashMap<Option<Url>, reqwest::Client>>,
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<ProxyRotation>,
+}
+
+// A session pool per proxy, like HttpFetcher's clients
+pub struct WebDriverFetcher {
+    config: DriverConfig,
+    pools: Mutex<HashMap<Option<Url>, Arc<SessionPool>>>,
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<ProxyRotation>,
+}
+
+#[derive(Default)]
+pub struct StubFetcher {
+    pages: HashMap<String, String>,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+pub fn new_fetcher(
+    backend: Backend,
+    driver: &DriverConfig,
+    proxies: Arc<ProxyRotation>,
+) -> Result<Arc<dyn Fetcher>> {
+    let res: Arc<dyn Fetcher> = match backend {
+        Backend::Http => Arc::new(RetryFetcher::new(
+            HttpFetcher::try_new()?.with_proxies(proxies),
+            RetryPolicy::default(),
+        )),
+        Backend::WebDriver => Arc::new(RetryFetcher::new(
+            WebDriverFetcher::new(driver).with_proxies(proxies),
+            RetryPolicy::default(),
+        )),
+    };
+
+    Ok(res)
+}
+
+fn try_select(url: &str, html: &str, css: &str) -> Result<String> {
+    let selector = selector(css)?;
+
+    let res = Html::parse_document(html)
+        .select(&selector)
+        .next()
+        .ok_or_else(|| SpiderError::missing_element(url, css))?
+        .html();
+
+    Ok(res)
+}
+
+fn try_http_client(proxy: Option<&Url>) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        // A hung request becomes a timeout error, which `RetryFetcher` retries
+        .timeout(Duration::from_secs(30));
+
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .map_err(|e| SpiderError::parse(format!("proxy {}", proxy), e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    builder
+        .build()
+        .map_err(|e| SpiderError::config("the HTTP client", e))
+}
+
+impl HttpFetcher {
+    pub fn try_new() -> Result<Self> {
+        let client = try_http_client(None)?;
+
+        Ok(HttpFetcher {
+            clients: Mutex::new(HashMap::from([(None, client)])),
+            limiter: RateLimiter::global(),
+            proxies: Arc::new(ProxyRotation::direct()),
+        })
+    }
+
+    pub fn with_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    pub fn with_proxies(mut self, proxies: Arc<ProxyRotation>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    fn try_client(&self, proxy: Option<&Url>) -> Result<reqwest::Client> {
+        let mut clients = self.clients.lock().unwrap();
+
+        if let Some(client) = clients.get(&proxy.cloned()) {
+            return Ok(client.clone());
+        }
+
+        let client = try_http_client(proxy)?;
+        clients.insert(proxy.cloned(), client.clone());
+
+        Ok(client)
+    }
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        let html = self.try_fetch_text(url).await?;
+
+        try_select(url, html.as_str(), css)
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        self.limiter.acquire(url).await;
+        let proxy = self.proxies.try_pick(url)?;
+        let client = self.try_client(proxy.as_ref())?;
+
+        let res = async {
+            client
+                .get(url)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| SpiderError::fetch(url, e))?
+                .text()
+                .await
+                .map_err(|e| SpiderError::fetch(url, e))
+        }
+        .await;
+
+        self.proxies.record(proxy.as_ref(), &res);
+
+        res
+    }
+}
+
+impl WebDriverFetcher {
+    // Endpoint, capabilities, pool size and proxies all come from the config. Each proxy gets
+    // its own pool of up to `config.sessions()` browsers
+    pub fn new(config: &DriverConfig) -> Self {
+        WebDriverFetcher {
+            config: config.clone(),
+            pools: Mutex::new(HashMap::new()),
+            limiter: RateLimiter::global(),
+            proxies: Arc::new(ProxyRotation::new(config.proxies().clone())),
+        }
+    }
+
+    pub fn with_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    // To share proxy health with other fetchers; replaces the proxies from the config
+    pub fn with_proxies(mut self, proxies: Arc<ProxyRotation>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    // The pool whose browsers go through `proxy`
+    pub fn pool(&self, proxy: Option<&Url>) -> Arc<SessionPool> {
+        self.pools
+            .lock()
+            .unwrap()
+            .entry(proxy.cloned())
+            .or_insert_with(|| {
+                let mut capabilities = self.config.capabilities().clone();
+                if proxy.is_some() {
+                    capabilities = capabilities.with_proxy(proxy.cloned());
+                }
+
+                SessionPool::from_config(&self.config.clone().with_capabilities(capabilities))
+            })
+            .clone()
+    }
+}
+
+// Anything but "not on the page (yet)" may have left the session in a bad state
+fn is_session_error(e: &SpiderError) -> bool {
+    !matches!(
+        e,
+        SpiderError::MissingElement { .. }
+            | SpiderError::WaitTimeout { .. }
+            | SpiderError::Parse { .. }
+    )
+}
+
+#[async_trait]
+impl Fetcher for WebDriverFetcher {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        // Before borrowing a session, so waiting doesn't keep a browser from other hosts
+        self.limiter.acquire(url).await;
+        let proxy = self.proxies.try_pick(url)?;
+        let c = self.pool(proxy.as_ref()).try_get().await?;
+
+        let res = async {
+            c.goto(url)
+                .await
+                .map_err(|e| SpiderError::navigation(url, e))?;
+
+            // Wait rather than find, in case the element is rendered client side
+            let element = c
+                .wait()
+                .for_element(Locator::Css(css))
+                .await
+                .map_err(|e| match e {
+                    CmdError::WaitTimeout => SpiderError::wait_timeout(url, css),
+                    CmdError::NoSuchElement(_) => SpiderError::missing_element(url, css),
+                    e => SpiderError::fetch(url, e),
+                })?;
+
+            element
+                .html(false)
+                .await
+                .map_err(|e| SpiderError::fetch(url, e))
+        }
+        .await;
+
+        if res.as_ref().err().is_some_and(is_session_error) {
+            c.discard().await;
+        }
+        self.proxies.record(proxy.as_ref(), &res);
+
+        res
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        self.limiter.acquire(url).await;
+        let proxy = self.proxies.try_pick(url)?;
+        let c = self.pool(proxy.as_ref()).try_get().await?;
+
+        let res = async {
+            // Browsers wrap raw JSON in their own viewer, so load the site and fetch it from there
+            let origin = url::Url::parse(url)
+                .map_err(|e| SpiderError::parse(format!("url {}", url), e))?
+                .origin()
+                .ascii_serialization();
+
+            c.goto(origin.as_str())
+                .await
+                .map_err(|e| SpiderError::navigation(&origin, e))?;
+
+            let text = c
+                .execute(
+                    "return fetch(arguments[0]).then(res => res.text());",
+                    vec![serde_json::Value::from(url)],
+                )
+                .await
+                .map_err(|e| SpiderError::fetch(url, e))?;
+
+            let text = text
+                .as_str()
+                .ok_or_else(|| SpiderError::parse(url, "response did not come back as text"))?
+                .to_string();
+
+            Ok(text)
+        }
+        .await;
+
+        if res.as_ref().err().is_some_and(is_session_error) {
+            c.discard().await;
+        }
+        self.proxies.record(proxy.as_ref(), &res);
+
+        res
+    }
+
+    // Sessions stay open between fetches, in every pool
+    async fn try_close(&self) -> Result<()> {
+        let pools = self
+            .pools
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut res = Ok(());
+        for pool in pools {
+            res = res.and(pool.try_close().await);
+        }
+
+        res
+    }
+}
+
+// So the decorators (RetryFetcher, CacheFetcher) can wrap what `new_fetcher` returns
+#[async_trait]
+impl<F: Fetcher + ?Sized> Fetcher for Arc<F> {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        self.as_ref().try_fetch_element(url, css).await
+    }
+
+    async fn try_fetch_element_dated(&self, url: &str, css: &str) -> Result<Fetched> {
+        self.as_ref().try_fetch_element_dated(url, css).await
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        self.as_ref().try_fetch_text(url).await
+    }
+
+    async fn try_close(&self) -> Result<()> {
+        self.as_ref().try_close().await
+    }
+}
+
+impl StubFetcher {
+    pub fn new() -> Self {
+        StubFetcher::default()
+    }
+
+    pub fn with_page(mut self, url: &str, body: &str) -> Self {
+        self.pages.insert(url.to_string(), body.to_string());
+        self
+    }
+
+    pub fn try_with_file(self, url: &str, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let body = std::fs::read_to_string(path)
+            .map_err(|e| SpiderError::fetch(path.display().to_string().as_str(), e))?;
+
+        Ok(self.with_page(url, body.as_str()))
+    }
+}
+
+#[async_trait]
+impl Fetcher for StubFetcher {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        let html = self.try_fetch_text(url).await?;
+
+        try_select(url, html.as_str(), css)
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        self.pages.get(url).cloned().ok_or_else(|| {
+            SpiderError::fetch(
+                url,
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no stub page"),
+            )
+        })
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_HTML: &str = r#"
+<html>
+<head><title>SomaFM Groove Salad Song History</title></head>
+<body>
+<div id="content">
+  <h1>Groove Salad: Songs Played Recently</h1>
+  <div id="playinc">
+    <table><tbody><tr><td>14:21:19</td></tr></tbody></table>
+  </div>
+</div>
+</body>
+</html>
+"#;
+
+    #[test]
+    fn test_01_parse_backend() {
+        assert_eq!("http".parse::<Backend>().unwrap(), Backend::Http);
+        assert_eq!("webdriver".parse::<Backend>().unwrap(), Backend::WebDriver);
+        assert!("curl".parse::<Backend>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_02_stub_fetch_element() {
+        let fetcher = StubFetcher::new().with_page("https://example.com/", PAGE_HTML);
+
+        let res = fetcher
+            .try_fetch_element("https://example.com/", "#playinc")
+            .await
+            .unwrap();
+
+        assert!(res.starts_with(r#"<div id="playinc">"#));
+        assert!(res.contains("<td>14:21:19</td>"));
+
+        assert!(matches!(
+            fetcher
+                .try_fetch_element("https://example.com/", "#missing")
+                .await,
+            Err(SpiderError::MissingElement { .. })
+        ));
+        assert!(matches!(
+            fetcher.try_fetch_text("https://example.com/other").await,
+            Err(SpiderError::Fetch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_03_stub_fetch_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-demo-fetch-{}-test_03.html",
+            std::process::id()
+        ));
+        std::fs::write(&path, PAGE_HTML).unwrap();
+
+        let fetcher = StubFetcher::new()
+            .try_with_file("https://example.com/", &path)
+            .unwrap();
+
+        let res = fetcher
+            .try_fetch_text("https://example.com/")
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res, PAGE_HTML);
+        assert!(StubFetcher::new()
+            .try_with_file("https://example.com/", "/does/not/exist.html")
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_04_http_fetch_element() {
+        let fetcher = HttpFetcher::try_new().unwrap();
+
+        let res = fetcher
+            .try_fetch_element(
+                "https://somafm.com/groovesalad/songhistory.html",
+                "#playinc",
+            )
+            .await
+            .unwrap();
+
+        println!("HTML: {}", res);
+
+        assert!(res.contains("<table"))
+    }
+}
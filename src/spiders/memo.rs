@@ -0,0 +1,483 @@
+/*
+    The same songs come round on SomaFM all day, so remember which video each one resolved to
+    instead of searching YouTube again every time it plays:
+
+        ResolutionMemo    artist + song -> video, kept in an NDJSON file, one entry per line.
+                          Songs match by their normalized artist and song, regardless of
+                          case, "feat." credits, remix tags and cut off brackets
+        MemoEntry         the video, when it was resolved, how confident the pick was, and
+                          whether it is pinned
+        Memoized          a resolver that asks the memo first, and another resolver after that
+
+    Entries that came from a search can be expired, so their songs get searched again. Pinned
+    entries are set by hand: no search replaces them and expiring leaves them alone, only
+    `try_forget` removes them. Every change rewrites the whole file.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::normalize::NormalizedItem;
+use crate::spiders::score::MatchScorer;
+use crate::spiders::somafm::{rfc3339, PlaylistItem};
+use crate::spiders::youtube::{self, Resolve, TupleHash, VideoCandidate};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+// One line of the memo file; artist and song are kept as they were first seen
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MemoEntry {
+    artist: String,
+    song: String,
+    video_id: String,
+    #[serde(with = "rfc3339")]
+    resolved_at: DateTime<Utc>,
+    // The match score, from 0 to 1; None on lines added by hand without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(default)]
+    pinned: bool,
+}
+
+type MemoKey = (String, String);
+
+#[derive(Debug, Default)]
+pub struct ResolutionMemo {
+    // None keeps everything in memory
+    path: Option<PathBuf>,
+    entries: HashMap<MemoKey, MemoEntry>,
+}
+
+pub struct Memoized<'a, R> {
+    memo: &'a ResolutionMemo,
+    fallback: &'a R,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+fn key(artist: &str, song: &str) -> MemoKey {
+    NormalizedItem::new(artist, song).key()
+}
+
+impl MemoEntry {
+    pub fn artist(&self) -> &str {
+        &self.artist
+    }
+
+    pub fn song(&self) -> &str {
+        &self.song
+    }
+
+    pub fn video_id(&self) -> &str {
+        &self.video_id
+    }
+
+    pub fn video_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.video_id)
+    }
+
+    pub fn resolved_at(&self) -> DateTime<Utc> {
+        self.resolved_at
+    }
+
+    pub fn confidence(&self) -> Option<f64> {
+        self.confidence
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn key(&self) -> MemoKey {
+        key(&self.artist, &self.song)
+    }
+}
+
+impl std::fmt::Display for MemoEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {} - {}  {}",
+            self.resolved_at.format("%Y-%m-%d %H:%M:%S"),
+            self.artist,
+            self.song,
+            self.video_url()
+        )?;
+
+        if let Some(confidence) = self.confidence {
+            write!(f, " ({:.2})", confidence)?;
+        }
+        if self.pinned {
+            write!(f, " (pinned)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ResolutionMemo {
+    pub fn in_memory() -> Self {
+        ResolutionMemo::default()
+    }
+
+    // Creates the file on the first change if it doesn't exist yet
+    pub fn try_open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut res = ResolutionMemo {
+            path: Some(path.to_path_buf()),
+            ..ResolutionMemo::default()
+        };
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(res),
+            Err(e) => return Err(SpiderError::memo(path, e)),
+        };
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| SpiderError::memo(path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = serde_json::from_str::<MemoEntry>(line.as_str()).map_err(|e| {
+                SpiderError::parse(format!("{} line {}", path.display(), index + 1), e)
+            })?;
+
+            // A line added by hand overrides what came before it
+            res.entries.insert(entry.key(), entry);
+        }
+
+        Ok(res)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Sorted by artist, then song
+    pub fn entries(&self) -> Vec<&MemoEntry> {
+        let mut res = self.entries.iter().collect::<Vec<_>>();
+        res.sort_by(|a, b| a.0.cmp(b.0));
+
+        res.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    pub fn get(&self, artist: &str, song: &str) -> Option<&MemoEntry> {
+        self.entries.get(&key(artist, song))
+    }
+
+    // The songs that still need a search
+    pub fn unresolved(&self, playlist: &[PlaylistItem]) -> Vec<PlaylistItem> {
+        playlist
+            .iter()
+            .filter(|item| self.get(item.artist(), item.song()).is_none())
+            .cloned()
+            .collect()
+    }
+
+    // Remembers the best match for every song that was searched and found a good enough one.
+    // Returns how many entries were added or replaced
+    pub fn try_record(
+        &mut self,
+        playlist: &[PlaylistItem],
+        results: &TupleHash<String, Vec<VideoCandidate>>,
+        scorer: &MatchScorer,
+    ) -> Result<usize> {
+        let now = Utc::now();
+        let mut res = 0;
+
+        for item in playlist {
+            let Some((candidate, score)) = scorer.best(item, results.candidates(item)) else {
+                continue;
+            };
+
+            let key = key(item.artist(), item.song());
+            let known = self
+                .entries
+                .get(&key)
+                .is_some_and(|entry| entry.pinned || entry.video_id == candidate.video_id());
+            if known {
+                continue;
+            }
+
+            let entry = MemoEntry {
+                artist: item.artist().to_string(),
+                song: item.song().to_string(),
+                video_id: candidate.video_id().to_string(),
+                resolved_at: now,
+                confidence: Some(score),
+                pinned: false,
+            };
+
+            self.entries.insert(key, entry);
+            res += 1;
+        }
+
+        if res > 0 {
+            self.try_save()?;
+        }
+
+        Ok(res)
+    }
+
+    // Always resolve this song to `video_id`
+    pub fn try_pin(&mut self, artist: &str, song: &str, video_id: &str) -> Result<()> {
+        let entry = MemoEntry {
+            artist: artist.to_string(),
+            song: song.to_string(),
+            video_id: video_id.to_string(),
+            resolved_at: Utc::now(),
+            confidence: Some(1.0),
+            pinned: true,
+        };
+        self.entries.insert(entry.key(), entry);
+
+        self.try_save()
+    }
+
+    // Keeps the video, but lets it expire like a searched one. False if there was no such song
+    pub fn try_unpin(&mut self, artist: &str, song: &str) -> Result<bool> {
+        let Some(entry) = self.entries.get_mut(&key(artist, song)) else {
+            return Ok(false);
+        };
+        entry.pinned = false;
+
+        self.try_save().map(|_| true)
+    }
+
+    // Pinned or not. False if there was no such song
+    pub fn try_forget(&mut self, artist: &str, song: &str) -> Result<bool> {
+        if self.entries.remove(&key(artist, song)).is_none() {
+            return Ok(false);
+        }
+
+        self.try_save().map(|_| true)
+    }
+
+    // Drops every entry that isn't pinned and was resolved before `before`. Returns how many
+    pub fn try_expire(&mut self, before: DateTime<Utc>) -> Result<usize> {
+        let len = self.entries.len();
+        self.entries
+            .retain(|_, entry| entry.pinned || entry.resolved_at >= before);
+
+        let res = len - self.entries.len();
+        if res > 0 {
+            self.try_save()?;
+        }
+
+        Ok(res)
+    }
+
+    // Write then rename, so a crash never leaves half a memo behind
+    fn try_save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let res = || -> std::io::Result<()> {
+            let tmp = path.with_extension("tmp");
+            let mut out = BufWriter::new(File::create(&tmp)?);
+
+            for entry in self.entries() {
+                serde_json::to_writer(&mut out, entry)?;
+                writeln!(out)?;
+            }
+
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            std::fs::rename(&tmp, path)
+        };
+
+        res().map_err(|e| SpiderError::memo(path, e))
+    }
+}
+
+impl Resolve for ResolutionMemo {
+    fn try_resolve(&self, item: &PlaylistItem) -> Result<String> {
+        self.get(item.artist(), item.song())
+            .map(MemoEntry::video_url)
+            .ok_or_else(|| SpiderError::Unresolved {
+                query: youtube::search_terms(item),
+            })
+    }
+}
+
+impl<'a, R: Resolve> Memoized<'a, R> {
+    pub fn new(memo: &'a ResolutionMemo, fallback: &'a R) -> Self {
+        Memoized { memo, fallback }
+    }
+}
+
+impl<R: Resolve> Resolve for Memoized<'_, R> {
+    fn try_resolve(&self, item: &PlaylistItem) -> Result<String> {
+        self.memo
+            .try_resolve(item)
+            .or_else(|_| self.fallback.try_resolve(item))
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::somafm::{test_played_at, test_song};
+    use crate::spiders::youtube::{TupleVec, YPlaylistItem};
+    use chrono::Duration;
+
+    const SEARCH_HTML: &str = r#"
+<ytd-video-renderer><a id="video-title" title="Afterlife - Sun Dance" href="/watch?v=8HY3yD0sA8Y"></a></ytd-video-renderer>
+<ytd-video-renderer><a id="video-title" title="Sun Dance (live)" href="/watch?v=Qm4bXo2vL0c"></a></ytd-video-renderer>
+<ytd-video-renderer><a id="video-title" title="Groove Matter - 97 Ways" href="/watch?v=Zx1hfzc8vOo"></a></ytd-video-renderer>
+"#;
+
+    fn results(items: &[PlaylistItem]) -> TupleHash<String, Vec<VideoCandidate>> {
+        let candidates = youtube::try_scrape_search_html(SEARCH_HTML).unwrap();
+
+        items
+            .iter()
+            .map(|item| (youtube::search_terms(item), candidates.clone()))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    #[test]
+    fn test_01_record_lookup() {
+        let mut memo = ResolutionMemo::in_memory();
+        let playlist = vec![
+            test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+            test_song(test_played_at(14, 21, 19), "Groove Matter", "97 Ways"),
+        ];
+
+        assert_eq!(memo.unresolved(&playlist).len(), 2);
+        assert_eq!(
+            memo.try_record(&playlist[..1], &results(&playlist), &MatchScorer::new())
+                .unwrap(),
+            1
+        );
+        // Nothing new the second time round
+        assert_eq!(
+            memo.try_record(&playlist[..1], &results(&playlist), &MatchScorer::new())
+                .unwrap(),
+            0
+        );
+
+        let entry = memo.get("  AFTERLIFE ", "sun   dance").unwrap();
+        assert_eq!(entry.video_id(), "8HY3yD0sA8Y");
+        assert_eq!(entry.artist(), "Afterlife");
+        assert!(entry.confidence().unwrap() >= MatchScorer::new().threshold());
+        assert_eq!(memo.unresolved(&playlist), vec![playlist[1].clone()]);
+
+        // The memo answers first; the search results only for what it doesn't know
+        let fallback = HashMap::<String, String>::from([(
+            "Groove Matter + 97 Ways".to_string(),
+            "https://www.youtube.com/watch?v=sjqLVcPd18E".to_string(),
+        )]);
+        let res = TupleVec::<YPlaylistItem>::try_from((
+            playlist.clone(),
+            &Memoized::new(&memo, &fallback),
+        ))
+        .unwrap()
+        .into_inner();
+
+        assert_eq!(
+            res[0].video(),
+            "https://www.youtube.com/watch?v=8HY3yD0sA8Y"
+        );
+        assert_eq!(
+            res[1].video(),
+            "https://www.youtube.com/watch?v=sjqLVcPd18E"
+        );
+        assert!(memo.try_resolve(&playlist[1]).is_err());
+    }
+
+    #[test]
+    fn test_02_pin_expire() {
+        let mut memo = ResolutionMemo::in_memory();
+        let playlist = vec![
+            test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+            test_song(test_played_at(14, 21, 19), "Groove Matter", "97 Ways"),
+        ];
+
+        memo.try_pin("afterlife", "sun dance", "Qm4bXo2vL0c")
+            .unwrap();
+        assert_eq!(
+            memo.try_record(&playlist, &results(&playlist), &MatchScorer::new())
+                .unwrap(),
+            1
+        );
+
+        // A search never replaces a pinned video
+        let pinned = memo.get("Afterlife", "Sun Dance").unwrap();
+        assert_eq!(pinned.video_id(), "Qm4bXo2vL0c");
+        assert_eq!(pinned.confidence(), Some(1.0));
+        assert!(pinned.is_pinned());
+
+        assert_eq!(memo.try_expire(Utc::now() - Duration::days(1)).unwrap(), 0);
+        assert_eq!(memo.try_expire(Utc::now() + Duration::days(1)).unwrap(), 1);
+        assert_eq!(memo.len(), 1);
+
+        assert!(memo.try_unpin("Afterlife", "Sun Dance").unwrap());
+        assert_eq!(memo.try_expire(Utc::now() + Duration::days(1)).unwrap(), 1);
+        assert!(memo.is_empty());
+
+        memo.try_pin("Afterlife", "Sun Dance", "Qm4bXo2vL0c")
+            .unwrap();
+        assert!(memo.try_forget("AFTERLIFE", "SUN DANCE").unwrap());
+        assert!(!memo.try_forget("Afterlife", "Sun Dance").unwrap());
+    }
+
+    #[test]
+    fn test_03_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-demo-memo-{}-test_03.ndjson",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let playlist = vec![
+            test_song(test_played_at(14, 21, 19), "Afterlife", "Sun Dance"),
+            test_song(test_played_at(14, 21, 19), "Groove Matter", "97 Ways"),
+        ];
+
+        let mut memo = ResolutionMemo::try_open(&path).unwrap();
+        assert!(memo.is_empty());
+        memo.try_record(&playlist, &results(&playlist), &MatchScorer::new())
+            .unwrap();
+        memo.try_pin("Groove Matter", "97 Ways", "sjqLVcPd18E")
+            .unwrap();
+
+        let memo = ResolutionMemo::try_open(&path).unwrap();
+        let entries = memo.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].artist(), "Afterlife");
+        assert_eq!(entries[1].video_id(), "sjqLVcPd18E");
+        assert!(entries[1].is_pinned());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.clone() + "{not json}\n").unwrap();
+        let res = ResolutionMemo::try_open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text.lines().count(), 2);
+        assert!(matches!(res, Err(SpiderError::Parse { .. })));
+    }
+}
@@ -1,3 +1,18 @@
 //pub mod spiders;
+pub mod archive;
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod memo;
+pub mod normalize;
+pub mod playlist;
+pub mod poll;
+pub mod proxy;
+pub mod rate;
+pub mod retry;
+pub mod score;
+pub mod session;
 pub mod somafm;
 pub mod youtube;
\ No newline at end of file
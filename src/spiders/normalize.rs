@@ -0,0 +1,416 @@
+/*
+    SomaFM's metadata isn't made for searching. Titles are cut off mid-word, often inside a
+    bracket ("Ore Corymb [Bursting Rainbow C"), carry remix tags and "feat." credits, and some
+    rows were stored in the wrong encoding ("CafŽ Del Mar"). `NormalizedItem` cleans artist and
+    song up into what a search needs:
+
+        repair_mojibake     UTF-8 read as Windows-1252 ("CafÃ©") and Mac Roman read as
+                            Windows-1252 ("CafŽ") both go back to "Café"
+        strip_truncated     drops a bracket that is never closed; whatever was cut off in it
+                            would only throw a search off
+        featured            "A feat. B", "A ft. B & C", "Song (feat. B)" -> ["B", "C"]
+        remixers            "Song (B Remix)", "Song [B Rework]", "Song - B Remix" -> ["B"]
+
+    Brackets that only decorate the title ("(Original Mix)", "(Radio Edit)", "[Remastered 2011]")
+    are dropped as well; anything else in brackets, like "(Live)", stays part of the song.
+    The YouTube search goes by `search_key()` and the resolution memo by `key()`, so two spellings
+    of one song share a search and a memo entry.
+*/
+
+use crate::spiders::somafm::PlaylistItem;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NormalizedItem {
+    artist: String,
+    song: String,
+    featured: Vec<String>,
+    remixers: Vec<String>,
+    truncated: bool,
+}
+
+// Words that start a list of featured artists
+const FEATURING: &[&str] = &["featuring", "feat.", "ft."];
+
+// ...and the same without the dot, which can just as well end a name ("Little Feat"). They only
+// count in brackets, or when a name follows and there is no dotted one
+const FEATURING_BARE: &[&str] = &["feat", "ft"];
+
+// The last word of a bracket naming a remix: "(Kiln Remix)"
+const REMIX: &[&str] = &["remix", "rmx", "rework", "re-edit", "bootleg"];
+
+const DECORATIONS: &[&str] = &[
+    "original mix",
+    "extended mix",
+    "club mix",
+    "radio edit",
+    "radio version",
+    "album version",
+    "single version",
+    "extended version",
+    "original version",
+    "edit",
+];
+
+// What the bytes 0x80..0x9F are in Windows-1252; the rest of 0x80..0xFF is the same as Latin-1
+const CP1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+// Mac Roman letters that come out as Windows-1252 symbols and capitals. The lowercase ones
+// (š, œ, ž) are left alone, they are real letters in too many names
+const MAC_ROMAN: &[(char, char)] = &[
+    ('‡', 'á'),
+    ('ˆ', 'à'),
+    ('‰', 'â'),
+    ('Š', 'ä'),
+    ('‹', 'ã'),
+    ('Œ', 'å'),
+    ('Ž', 'é'),
+    ('Ÿ', 'ü'),
+];
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+// "  Groove  MATTER " -> "groove matter"
+pub fn fold(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn cp1252_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        0..=0x7F | 0xA0..=0xFF => Some(c as u8),
+        _ => CP1252
+            .iter()
+            .position(|other| *other == c)
+            .map(|index| 0x80 + index as u8),
+    }
+}
+
+pub fn repair_mojibake(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+
+    // Text that really is Latin-1 almost never happens to be valid UTF-8 as well
+    let bytes = text.chars().map(cp1252_byte).collect::<Option<Vec<u8>>>();
+    if let Some(Ok(res)) = bytes.map(String::from_utf8) {
+        return res;
+    }
+
+    // Only right after a lowercase letter, where none of these belong in a real word
+    let mut res = String::with_capacity(text.len());
+    let mut prev = None;
+    for c in text.chars() {
+        let repaired = MAC_ROMAN
+            .iter()
+            .find(|(wrong, _)| *wrong == c)
+            .filter(|_| prev.is_some_and(char::is_lowercase))
+            .map_or(c, |(_, right)| *right);
+
+        res.push(repaired);
+        prev = Some(repaired);
+    }
+
+    res
+}
+
+// "Ore Corymb [Bursting Rainbow C" -> ("Ore Corymb", true)
+pub fn strip_truncated(text: &str) -> (String, bool) {
+    let mut open = vec![];
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => open.push(index),
+            ')' | ']' | '}' => {
+                open.pop();
+            }
+            _ => (),
+        }
+    }
+
+    match open.first() {
+        Some(index) => (text[..*index].trim_end().to_string(), true),
+        None => (text.to_string(), false),
+    }
+}
+
+// Splits off the top-level brackets: "Song (A) [B]" -> ("Song", ["A", "B"])
+fn brackets(text: &str) -> (String, Vec<String>) {
+    let mut rest = String::new();
+    let mut groups = vec![];
+    let mut group = String::new();
+    let mut depth = 0;
+
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '{' => {
+                if depth > 0 {
+                    group.push(c);
+                }
+                depth += 1;
+            }
+            ')' | ']' | '}' if depth > 0 => {
+                depth -= 1;
+                match depth {
+                    0 => groups.push(std::mem::take(&mut group)),
+                    _ => group.push(c),
+                }
+            }
+            _ if depth > 0 => group.push(c),
+            _ => rest.push(c),
+        }
+    }
+
+    (collapse(rest.as_str()), groups)
+}
+
+// "B, C & D and E" -> ["B", "C", "D", "E"]
+fn names(text: &str) -> Vec<String> {
+    text.split([',', '&'])
+        .flat_map(|part| part.split(" and "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_featuring(word: &str) -> bool {
+    let word = word.to_lowercase();
+
+    FEATURING.contains(&word.as_str()) || FEATURING_BARE.contains(&word.as_str())
+}
+
+// "A feat. B & C" -> ("A", ["B", "C"]); "Little Feat" stays as it is
+fn split_featuring(text: &str) -> (String, Vec<String>) {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let at = words
+        .iter()
+        .position(|word| FEATURING.contains(&word.to_lowercase().as_str()))
+        .or_else(|| {
+            (0..words.len().saturating_sub(1))
+                .find(|&at| FEATURING_BARE.contains(&words[at].to_lowercase().as_str()))
+        });
+
+    match at {
+        Some(at) if at > 0 => (words[..at].join(" "), names(&words[at + 1..].join(" "))),
+        _ => (collapse(text), vec![]),
+    }
+}
+
+// "Kiln Remix" -> Some("Kiln"); "Original Mix" -> None
+fn remixer(text: &str) -> Option<String> {
+    let (name, last) = text.trim().rsplit_once(' ')?;
+
+    Some(name.trim().to_string())
+        .filter(|_| REMIX.contains(&last.to_lowercase().as_str()))
+        .filter(|name| !name.eq_ignore_ascii_case("original"))
+}
+
+fn is_decoration(text: &str) -> bool {
+    let text = fold(text);
+
+    DECORATIONS.contains(&text.as_str()) || text.contains("remaster")
+}
+
+impl NormalizedItem {
+    pub fn new(artist: &str, song: &str) -> Self {
+        let artist = collapse(repair_mojibake(artist).as_str());
+        let repaired = collapse(repair_mojibake(song).as_str());
+
+        let (song, truncated) = strip_truncated(repaired.as_str());
+        let (song, groups) = brackets(song.as_str());
+        let (mut song, mut featured) = split_featuring(song.as_str());
+        let (main_artist, credited) = split_featuring(artist.as_str());
+        featured.splice(0..0, credited);
+        let mut remixers = vec![];
+
+        for group in groups {
+            let (first, rest) = group.trim().split_once(' ').unwrap_or((group.trim(), ""));
+
+            if is_featuring(first) {
+                featured.extend(names(rest));
+            } else if let Some(name) = remixer(group.as_str()) {
+                remixers.extend(names(name.as_str()));
+            } else if !is_decoration(group.as_str()) {
+                song = format!("{} ({})", song, group.trim());
+            }
+        }
+
+        // "Song - Kiln Remix", "Song - Radio Edit"
+        if let Some((title, suffix)) = song.clone().rsplit_once(" - ") {
+            if let Some(name) = remixer(suffix) {
+                remixers.extend(names(name.as_str()));
+                song = title.to_string();
+            } else if is_decoration(suffix) {
+                song = title.to_string();
+            }
+        }
+
+        // Better a messy search than none at all
+        if song.is_empty() {
+            song = repaired.replace(['(', ')', '[', ']', '{', '}'], "");
+        }
+
+        NormalizedItem {
+            artist: match main_artist.is_empty() {
+                true => artist,
+                false => main_artist,
+            },
+            song: collapse(song.as_str()),
+            featured,
+            remixers,
+            truncated,
+        }
+    }
+
+    pub fn artist(&self) -> &str {
+        &self.artist
+    }
+
+    pub fn song(&self) -> &str {
+        &self.song
+    }
+
+    pub fn featured(&self) -> &[String] {
+        &self.featured
+    }
+
+    pub fn remixers(&self) -> &[String] {
+        &self.remixers
+    }
+
+    // The song title was cut off in a bracket, which was dropped
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // Same format as the README example: "Groove Matter + 97 Ways"
+    pub fn search_key(&self) -> String {
+        format!("{} + {}", self.artist, self.song)
+    }
+
+    // Case and spacing folded, for telling whether two songs are the same
+    pub fn key(&self) -> (String, String) {
+        (fold(&self.artist), fold(&self.song))
+    }
+}
+
+impl From<&PlaylistItem> for NormalizedItem {
+    fn from(item: &PlaylistItem) -> Self {
+        NormalizedItem::new(item.artist(), item.song())
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_01_mojibake() {
+        assert_eq!(
+            repair_mojibake("CafŽ Del Mar Vol. 4"),
+            "Café Del Mar Vol. 4"
+        );
+        assert_eq!(repair_mojibake("CafÃ© Del Mar"), "Café Del Mar");
+        assert_eq!(repair_mojibake("Donâ€™t Stop"), "Don’t Stop");
+        assert_eq!(repair_mojibake("Bjšrk Ÿber Alles"), "Bjšrk Ÿber Alles");
+
+        // Already right
+        assert_eq!(repair_mojibake("Café Žižkov"), "Café Žižkov");
+        assert_eq!(repair_mojibake("Don’t Stop"), "Don’t Stop");
+        assert_eq!(repair_mojibake("Sun Dance"), "Sun Dance");
+    }
+
+    #[test]
+    fn test_02_truncated() {
+        assert_eq!(
+            strip_truncated("Ore Corymb [Bursting Rainbow C"),
+            ("Ore Corymb".to_string(), true)
+        );
+        assert_eq!(
+            strip_truncated("Twinewheel [Lost-Sides (1994)"),
+            ("Twinewheel".to_string(), true)
+        );
+        assert_eq!(
+            strip_truncated("97 Ways (Live)"),
+            ("97 Ways (Live)".to_string(), false)
+        );
+
+        let res = NormalizedItem::new("Lounge Deluxe", "This Is All I Ask (Oxygene Dow");
+        assert_eq!(res.song(), "This Is All I Ask");
+        assert!(res.is_truncated());
+        assert_eq!(res.search_key(), "Lounge Deluxe + This Is All I Ask");
+    }
+
+    #[test]
+    fn test_03_featured_remixers() {
+        let res = NormalizedItem::new("Bonobo feat. Andreya Triana", "The Keeper (Kiln Remix)");
+        assert_eq!(res.artist(), "Bonobo");
+        assert_eq!(res.song(), "The Keeper");
+        assert_eq!(res.featured(), ["Andreya Triana"]);
+        assert_eq!(res.remixers(), ["Kiln"]);
+        assert!(!res.is_truncated());
+
+        let res = NormalizedItem::new(
+            "Thievery Corporation",
+            "Lebanese Blonde (feat. Pam Bricker & Rob Myers) [Original Mix]",
+        );
+        assert_eq!(res.song(), "Lebanese Blonde");
+        assert_eq!(res.featured(), ["Pam Bricker", "Rob Myers"]);
+        assert!(res.remixers().is_empty());
+
+        let res = NormalizedItem::new("Afterlife", "Sun Dance - Groove Matter & Kiln Rework");
+        assert_eq!(res.song(), "Sun Dance");
+        assert_eq!(res.remixers(), ["Groove Matter", "Kiln"]);
+
+        // Part of the song's name, not a decoration
+        let res = NormalizedItem::new("Groove Matter", "97 Ways (Live) [Remastered 2011]");
+        assert_eq!(res.song(), "97 Ways (Live)");
+        assert_eq!(res.search_key(), "Groove Matter + 97 Ways (Live)");
+    }
+
+    #[test]
+    fn test_04_key() {
+        let a = NormalizedItem::new("AFTERLIFE ft. Kiln", "  sun   dance (Radio Edit)");
+        let b = NormalizedItem::new("Afterlife", "Sun Dance");
+
+        assert_eq!(a.key(), b.key());
+        assert_eq!(a.key(), ("afterlife".to_string(), "sun dance".to_string()));
+        assert_eq!(NormalizedItem::new("Kiln", "(Untitled").song(), "Untitled");
+    }
+
+    #[test]
+    fn test_05_feat_in_name() {
+        let res = NormalizedItem::new("Little Feat", "Dixie Chicken");
+        assert_eq!(res.artist(), "Little Feat");
+        assert!(res.featured().is_empty());
+
+        let res = NormalizedItem::new("Little Feat feat. Bonnie Raitt", "Dixie Chicken");
+        assert_eq!(res.artist(), "Little Feat");
+        assert_eq!(res.featured(), ["Bonnie Raitt"]);
+
+        // Without a dot, but followed by a name or in brackets
+        let res = NormalizedItem::new("Afterlife ft Kiln", "Sun Dance (feat Groove Matter)");
+        assert_eq!(res.artist(), "Afterlife");
+        assert_eq!(res.song(), "Sun Dance");
+        assert_eq!(res.featured(), ["Kiln", "Groove Matter"]);
+    }
+}
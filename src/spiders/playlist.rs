@@ -0,0 +1,518 @@
+/*
+    Playlist files for media players, written from scraped or resolved songs:
+
+        M3U8    extended M3U: "#EXTINF:<seconds>,<artist> - <song>", "#EXTALB:<album>", then the URL
+        XSPF    the XML playlist format: creator, title, album, duration (ms) and location per track
+
+    Songs that were never resolved point at the YouTube search for them instead of a video.
+    Durations come from the song history: a song lasted until the one above it started, so the
+    song playing now has none (written as -1 in M3U8 and left out of XSPF).
+*/
+
+use crate::spiders::archive::ArchivedItem;
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::memo::MemoEntry;
+use crate::spiders::somafm::{Channel, PlaylistItem, StationPlaylist};
+use crate::spiders::youtube::{self, YPlaylistItem};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::Write;
+use std::time::Duration;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaylistEntry {
+    location: String,
+    creator: String,
+    title: String,
+    album: String,
+    duration: Option<Duration>,
+}
+
+// Anything that can be turned into playlist entries. Takes the whole list, since durations
+// depend on the neighbouring rows
+pub trait ToPlaylist: Sized {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry>;
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl PlaylistEntry {
+    pub fn new(location: &str, creator: &str, title: &str) -> Self {
+        PlaylistEntry {
+            location: location.to_string(),
+            creator: creator.to_string(),
+            title: title.to_string(),
+            album: String::new(),
+            duration: None,
+        }
+    }
+
+    pub fn with_album(mut self, album: &str) -> Self {
+        self.album = album.to_string();
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn creator(&self) -> &str {
+        &self.creator
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn album(&self) -> &str {
+        &self.album
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+// Rows are newest first, so each song ends where the previous row begins
+fn history_durations<'a>(items: impl Iterator<Item = &'a PlaylistItem>) -> Vec<Option<Duration>> {
+    let mut newer: Option<&PlaylistItem> = None;
+
+    items
+        .map(|item| {
+            let res = newer
+                .and_then(|newer| (newer.played_at() - item.played_at()).to_std().ok())
+                .filter(|duration| !duration.is_zero());
+            newer = Some(item);
+            res
+        })
+        .collect()
+}
+
+fn entry_from_item(
+    item: &PlaylistItem,
+    location: &str,
+    duration: Option<Duration>,
+) -> PlaylistEntry {
+    PlaylistEntry::new(location, item.artist(), item.song())
+        .with_album(item.album())
+        .with_duration(duration)
+}
+
+impl ToPlaylist for PlaylistItem {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .zip(history_durations(items.iter()))
+            .map(|(item, duration)| {
+                let location = youtube::search_url(item.artist(), item.song());
+
+                entry_from_item(item, location.as_str(), duration)
+            })
+            .collect()
+    }
+}
+
+impl ToPlaylist for YPlaylistItem {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .zip(history_durations(
+                items.iter().map(YPlaylistItem::playlist_item),
+            ))
+            .map(|(item, duration)| entry_from_item(item.playlist_item(), item.video(), duration))
+            .collect()
+    }
+}
+
+impl ToPlaylist for StationPlaylist {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .flat_map(|station| PlaylistItem::playlist_entries(station.playlist()))
+            .collect()
+    }
+}
+
+// No durations: the rows can mix stations, and there are gaps between scrapes
+impl ToPlaylist for ArchivedItem {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .map(|archived| {
+                let item = archived.item();
+                let location = youtube::search_url(item.artist(), item.song());
+
+                entry_from_item(item, location.as_str(), None)
+            })
+            .collect()
+    }
+}
+
+impl ToPlaylist for MemoEntry {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .map(|entry| PlaylistEntry::new(&entry.video_url(), entry.artist(), entry.song()))
+            .collect()
+    }
+}
+
+// One entry per channel, pointing at its first stream
+impl ToPlaylist for Channel {
+    fn playlist_entries(items: &[Self]) -> Vec<PlaylistEntry> {
+        items
+            .iter()
+            .filter_map(|channel| {
+                let stream = channel.streams().first()?;
+
+                Some(PlaylistEntry::new(stream.url(), "SomaFM", channel.title()))
+            })
+            .collect()
+    }
+}
+
+pub fn try_write_m3u8(mut out: impl Write, entries: &[PlaylistEntry]) -> Result<()> {
+    let mut res = || -> std::io::Result<()> {
+        writeln!(out, "#EXTM3U")?;
+
+        for entry in entries {
+            let seconds = entry
+                .duration
+                .map(|duration| duration.as_secs() as i64)
+                .unwrap_or(-1);
+            // Line breaks would end the directive early
+            let one_line = |text: &str| text.replace(['\r', '\n'], " ");
+
+            writeln!(
+                out,
+                "#EXTINF:{},{} - {}",
+                seconds,
+                one_line(&entry.creator),
+                one_line(&entry.title)
+            )?;
+            if !entry.album.is_empty() {
+                writeln!(out, "#EXTALB:{}", one_line(&entry.album))?;
+            }
+            writeln!(out, "{}", one_line(&entry.location))?;
+        }
+
+        Ok(())
+    };
+
+    res().map_err(|e| SpiderError::export("m3u8", e))
+}
+
+// "#EXTINF" has one name field, split at its first " - ": an artist with " - " in their name
+// reads back with the rest of it in the title
+pub fn try_parse_m3u8(text: &str) -> Result<Vec<PlaylistEntry>> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    if lines.next() != Some("#EXTM3U") {
+        return Err(SpiderError::parse("m3u8", "missing #EXTM3U header"));
+    }
+
+    let mut res = Vec::new();
+    let mut info: Option<PlaylistEntry> = None;
+
+    for line in lines {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let (seconds, name) = extinf
+                .split_once(',')
+                .ok_or_else(|| SpiderError::parse("m3u8", format!("no title in \"{}\"", line)))?;
+            let seconds = seconds
+                .trim()
+                .parse::<i64>()
+                .map_err(|e| SpiderError::parse("m3u8", format!("\"{}\": {}", line, e)))?;
+            let (creator, title) = name.split_once(" - ").unwrap_or(("", name));
+
+            info = Some(
+                PlaylistEntry::new("", creator, title)
+                    .with_duration(u64::try_from(seconds).ok().map(Duration::from_secs)),
+            );
+        } else if let Some(album) = line.strip_prefix("#EXTALB:") {
+            info = info.map(|entry| entry.with_album(album));
+        } else if !line.starts_with('#') {
+            let mut entry = info
+                .take()
+                .unwrap_or_else(|| PlaylistEntry::new("", "", ""));
+            entry.location = line.to_string();
+            res.push(entry);
+        }
+    }
+
+    Ok(res)
+}
+
+pub fn try_write_xspf(
+    mut out: impl Write,
+    title: Option<&str>,
+    entries: &[PlaylistEntry],
+) -> Result<()> {
+    use quick_xml::escape::escape;
+
+    let mut res = || -> std::io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#
+        )?;
+        if let Some(title) = title {
+            writeln!(out, "  <title>{}</title>", escape(title))?;
+        }
+        writeln!(out, "  <trackList>")?;
+
+        for entry in entries {
+            writeln!(out, "    <track>")?;
+            writeln!(
+                out,
+                "      <location>{}</location>",
+                escape(&entry.location)
+            )?;
+            for (tag, text) in [
+                ("creator", &entry.creator),
+                ("title", &entry.title),
+                ("album", &entry.album),
+            ] {
+                if !text.is_empty() {
+                    writeln!(out, "      <{tag}>{}</{tag}>", escape(text.as_str()))?;
+                }
+            }
+            if let Some(duration) = entry.duration {
+                writeln!(out, "      <duration>{}</duration>", duration.as_millis())?;
+            }
+            writeln!(out, "    </track>")?;
+        }
+
+        writeln!(out, "  </trackList>")?;
+        writeln!(out, "</playlist>")?;
+
+        Ok(())
+    };
+
+    res().map_err(|e| SpiderError::export("xspf", e))
+}
+
+pub fn try_parse_xspf(text: &str) -> Result<Vec<PlaylistEntry>> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+
+    let mut res = Vec::new();
+    let mut track: Option<PlaylistEntry> = None;
+    let mut tag = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| SpiderError::parse("xspf", e))?;
+
+        match event {
+            Event::Start(start) => {
+                tag = start.local_name().as_ref().to_vec();
+                if tag == b"track" {
+                    track = Some(PlaylistEntry::new("", "", ""));
+                }
+            }
+            Event::Text(text) => {
+                // Only fields of a track; the playlist has a <title> of its own
+                let Some(entry) = track.as_mut() else {
+                    continue;
+                };
+                let text = text
+                    .unescape()
+                    .map_err(|e| SpiderError::parse("xspf", e))?
+                    .to_string();
+
+                match tag.as_slice() {
+                    b"location" => entry.location = text,
+                    b"creator" => entry.creator = text,
+                    b"title" => entry.title = text,
+                    b"album" => entry.album = text,
+                    b"duration" => {
+                        let millis = text.parse::<u64>().map_err(|e| {
+                            SpiderError::parse("xspf", format!("duration \"{}\": {}", text, e))
+                        })?;
+                        entry.duration = Some(Duration::from_millis(millis));
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(end) => {
+                tag.clear();
+                if end.local_name().as_ref() == b"track" {
+                    let entry = track
+                        .take()
+                        .unwrap_or_else(|| PlaylistEntry::new("", "", ""));
+                    if entry.location.is_empty() {
+                        return Err(SpiderError::parse("xspf", "track without a <location>"));
+                    }
+                    res.push(entry);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(res)
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::somafm::{Link, SOMAFM_TZ};
+    use chrono::TimeZone;
+
+    fn playlist() -> Vec<PlaylistItem> {
+        let played_at = |h, m, s| SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, h, m, s).unwrap();
+
+        vec![
+            PlaylistItem::builder(played_at(14, 21, 19), "Afterlife", "Dub, Strings & a Drum")
+                .album("Simply <Deluxe>")
+                .now_playing(true)
+                .try_build()
+                .unwrap(),
+            PlaylistItem::builder(played_at(14, 16, 50), "Groove Matter", "Sun Dance")
+                .album("Groovology")
+                .artist_link(Link::parse("https://groovematter.bandcamp.com/").unwrap())
+                .try_build()
+                .unwrap(),
+            PlaylistItem::try_new(played_at(14, 12, 6), "Sofa Lofa", "Magic Shopkeeper", "")
+                .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_01_entries() {
+        let res = PlaylistItem::playlist_entries(&playlist());
+
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].duration(), None);
+        assert_eq!(res[1].duration(), Some(Duration::from_secs(4 * 60 + 29)));
+        assert_eq!(res[2].duration(), Some(Duration::from_secs(4 * 60 + 44)));
+        assert_eq!(res[1].creator(), "Groove Matter");
+        assert_eq!(res[1].album(), "Groovology");
+        assert_eq!(
+            res[1].location(),
+            youtube::search_url("Groove Matter", "Sun Dance")
+        );
+    }
+
+    #[test]
+    fn test_02_m3u8_round_trip() {
+        let entries = PlaylistItem::playlist_entries(&playlist());
+        let mut out = Vec::new();
+
+        try_write_m3u8(&mut out, &entries).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        println!("M3U8: {}", text);
+
+        assert!(text.starts_with("#EXTM3U\n#EXTINF:-1,Afterlife - Dub, Strings & a Drum\n"));
+        assert!(text.contains("#EXTINF:269,Groove Matter - Sun Dance\n#EXTALB:Groovology\n"));
+        assert_eq!(try_parse_m3u8(text.as_str()).unwrap(), entries);
+
+        // A line break in the URL would start a new entry
+        let mut out = Vec::new();
+        let entry = PlaylistEntry::new("https://example.com/a\n#EXTINF:0,Fake", "A - B", "C");
+        try_write_m3u8(&mut out, &[entry]).unwrap();
+
+        let res = try_parse_m3u8(String::from_utf8(out).unwrap().as_str()).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].location(), "https://example.com/a #EXTINF:0,Fake");
+        assert_eq!((res[0].creator(), res[0].title()), ("A", "B - C"));
+    }
+
+    #[test]
+    fn test_03_xspf_round_trip() {
+        let entries = PlaylistItem::playlist_entries(&playlist());
+        let mut out = Vec::new();
+
+        try_write_xspf(&mut out, Some("Groove Salad"), &entries).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        println!("XSPF: {}", text);
+
+        assert!(text.contains("<album>Simply &lt;Deluxe&gt;</album>"));
+        assert!(text.contains("<duration>269000</duration>"));
+        assert_eq!(try_parse_xspf(text.as_str()).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_04_parse_errors() {
+        assert!(try_parse_m3u8("https://www.youtube.com/watch?v=Qp6pS3cvCKk").is_err());
+        assert!(try_parse_m3u8("#EXTM3U\n#EXTINF:soon,Afterlife - Sun Dance\nx").is_err());
+
+        // Players accept bare URLs too
+        let res = try_parse_m3u8("#EXTM3U\nhttps://www.youtube.com/watch?v=Qp6pS3cvCKk\n").unwrap();
+        assert_eq!(
+            res[0].location(),
+            "https://www.youtube.com/watch?v=Qp6pS3cvCKk"
+        );
+
+        assert!(try_parse_xspf(
+            "<playlist><trackList><track><title>Sun Dance</title></track></trackList></playlist>"
+        )
+        .is_err());
+        assert!(try_parse_xspf("<playlist><trackList><track></playlist>").is_err());
+    }
+
+    #[test]
+    fn test_05_resolved_round_trip() {
+        use crate::spiders::youtube::TupleVec;
+        use std::collections::HashMap;
+
+        let playlist = playlist();
+        let resolver = playlist
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                (
+                    youtube::search_terms(item),
+                    format!("https://www.youtube.com/watch?v=video{}", i),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let resolved = TupleVec::<YPlaylistItem>::try_from((playlist, &resolver))
+            .unwrap()
+            .into_inner();
+
+        let entries = YPlaylistItem::playlist_entries(&resolved);
+        assert_eq!(
+            entries[2].location(),
+            "https://www.youtube.com/watch?v=video2"
+        );
+        assert_eq!(
+            entries[2].duration(),
+            Some(Duration::from_secs(4 * 60 + 44))
+        );
+
+        let mut out = Vec::new();
+        try_write_m3u8(&mut out, &entries).unwrap();
+        assert_eq!(
+            try_parse_m3u8(String::from_utf8(out).unwrap().as_str()).unwrap(),
+            entries
+        );
+
+        let mut out = Vec::new();
+        try_write_xspf(&mut out, None, &entries).unwrap();
+        assert_eq!(
+            try_parse_xspf(String::from_utf8(out).unwrap().as_str()).unwrap(),
+            entries
+        );
+    }
+}
@@ -0,0 +1,597 @@
+/*
+    Long-running mode: re-fetch every station's song history on an interval and pass the rows that
+    weren't on the previous poll to a list of sinks (stdout, a log file, the archive, ...).
+
+        Poller      one task per station, all feeding the sinks through a channel
+        Sink        where new rows go; also told about failed polls
+        PollConfig  how often to poll, and how far to back off after failures
+
+    A station that fails to fetch or parse is retried after interval * 2^failures, capped at
+    `max_backoff`, while the other stations carry on. The poller stops when the `shutdown` future
+    completes, e.g. `shutdown_signal()` for SIGINT / SIGTERM.
+*/
+
+use crate::spiders::archive::Archive;
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::fetch::Fetcher;
+use crate::spiders::somafm::{self, PlaylistItem, ScrapeMode, Station};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollConfig {
+    pub interval: Duration,
+    pub max_backoff: Duration,
+    pub mode: ScrapeMode,
+}
+
+pub trait Sink: Send {
+    // Only called with rows that weren't on the station's previous poll
+    fn try_send(&mut self, station: &Station, items: &[PlaylistItem]) -> Result<()>;
+
+    fn on_error(&mut self, _station: &Station, _error: &SpiderError, _retry_in: Duration) {}
+}
+
+// Prints new rows, and failed polls to stderr
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+// Appends new rows to a text file, one line per song
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+pub struct ArchiveSink {
+    archive: Archive,
+}
+
+pub struct Poller {
+    fetcher: Arc<dyn Fetcher>,
+    stations: Vec<Station>,
+    config: PollConfig,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+enum PollEvent {
+    Polled {
+        station: Station,
+        items: Vec<PlaylistItem>,
+    },
+    Failed {
+        station: Station,
+        error: SpiderError,
+        retry_in: Duration,
+    },
+}
+
+type RowKey = (DateTime<Utc>, String, String);
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        PollConfig {
+            interval: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(15 * 60),
+            mode: ScrapeMode::Strict,
+        }
+    }
+}
+
+impl PollConfig {
+    // How long to wait after `failures` polls in a row went wrong
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.min(16));
+
+        self.interval
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Sink for StdoutSink {
+    fn try_send(&mut self, station: &Station, items: &[PlaylistItem]) -> Result<()> {
+        let mut out = std::io::stdout().lock();
+
+        for item in items {
+            writeln!(out, "{:<16} {}", station, item)
+                .map_err(|e| SpiderError::export("stdout", e))?;
+        }
+
+        Ok(())
+    }
+
+    fn on_error(&mut self, station: &Station, error: &SpiderError, retry_in: Duration) {
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(error);
+        while let Some(e) = source {
+            message = format!("{}: {}", message, e);
+            source = e.source();
+        }
+
+        eprintln!(
+            "Warning: {}: {}, retrying in {}s",
+            station,
+            message,
+            retry_in.as_secs()
+        );
+    }
+}
+
+impl FileSink {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileSink {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn try_send(&mut self, station: &Station, items: &[PlaylistItem]) -> Result<()> {
+        let what = self.path.display().to_string();
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| SpiderError::export(&what, e))?;
+
+        for item in items {
+            writeln!(file, "{:<16} {}", station, item)
+                .map_err(|e| SpiderError::export(&what, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ArchiveSink {
+    pub fn new(archive: Archive) -> Self {
+        ArchiveSink { archive }
+    }
+
+    pub fn archive(&self) -> &Archive {
+        &self.archive
+    }
+}
+
+impl Sink for ArchiveSink {
+    fn try_send(&mut self, station: &Station, items: &[PlaylistItem]) -> Result<()> {
+        self.archive.try_merge(station, items)?;
+
+        Ok(())
+    }
+}
+
+impl Poller {
+    pub fn new(fetcher: Arc<dyn Fetcher>, stations: &[Station], config: PollConfig) -> Self {
+        Poller {
+            fetcher,
+            stations: stations.to_vec(),
+            config,
+            sinks: Vec::new(),
+        }
+    }
+
+    pub fn with_sink(mut self, sink: impl Sink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    // Runs until `shutdown` completes or a sink fails. Rows already fetched are still delivered
+    // at shutdown, but not after a sink failed
+    pub async fn try_run(mut self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let (events, mut received) = mpsc::channel::<PollEvent>(self.stations.len().max(1) * 4);
+        let (stop, stopped) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+
+        for station in self.stations.iter().cloned() {
+            tasks.spawn(poll_station(
+                self.fetcher.clone(),
+                station,
+                self.config,
+                events.clone(),
+                stopped.clone(),
+            ));
+        }
+        drop(events);
+
+        tokio::pin!(shutdown);
+        let mut res = Ok(());
+
+        loop {
+            let event = tokio::select! {
+                _ = &mut shutdown, if !*stop.borrow() => {
+                    let _ = stop.send(true);
+                    continue;
+                }
+                event = received.recv() => event,
+            };
+
+            // Every task has exited and dropped its sender
+            let Some(event) = event else {
+                break;
+            };
+
+            // Keep receiving though, so no task is stuck sending
+            if res.is_err() {
+                continue;
+            }
+
+            if let Err(e) = self.try_dispatch(event) {
+                let _ = stop.send(true);
+                res = Err(e);
+            }
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(e) = joined {
+                std::panic::resume_unwind(e.into_panic());
+            }
+        }
+
+        res
+    }
+
+    fn try_dispatch(&mut self, event: PollEvent) -> Result<()> {
+        match event {
+            PollEvent::Polled { station, items } => {
+                for sink in self.sinks.iter_mut() {
+                    sink.try_send(&station, &items)?;
+                }
+            }
+            PollEvent::Failed {
+                station,
+                error,
+                retry_in,
+            } => {
+                for sink in self.sinks.iter_mut() {
+                    sink.on_error(&station, &error, retry_in);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn row_key(item: &PlaylistItem) -> RowKey {
+    (
+        item.played_at().with_timezone(&Utc),
+        item.artist().to_string(),
+        item.song().to_string(),
+    )
+}
+
+async fn try_poll_once(
+    fetcher: &dyn Fetcher,
+    station: &Station,
+    mode: ScrapeMode,
+) -> Result<Vec<PlaylistItem>> {
+    let html = somafm::try_get_playlist_dated(fetcher, station).await?;
+    let reference = html.fetched_at.with_timezone(&somafm::SOMAFM_TZ);
+    let playlist =
+        somafm::try_scrape_station_at(station.clone(), html.body.as_str(), reference, mode)?;
+
+    Ok(playlist.into_playlist())
+}
+
+async fn poll_station(
+    fetcher: Arc<dyn Fetcher>,
+    station: Station,
+    config: PollConfig,
+    events: mpsc::Sender<PollEvent>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    // Rows on the previous page; the page only shows the last ~20, so that's all we need
+    let mut seen = HashSet::<RowKey>::new();
+    let mut failures = 0;
+
+    while !*stopped.borrow() {
+        // A slow fetch shouldn't hold up the shutdown
+        let res = tokio::select! {
+            res = try_poll_once(fetcher.as_ref(), &station, config.mode) => res,
+            _ = stopped.changed() => return,
+        };
+
+        let (event, delay) = match res {
+            Ok(playlist) => {
+                failures = 0;
+
+                let items = playlist
+                    .iter()
+                    .filter(|item| !seen.contains(&row_key(item)))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                seen = playlist.iter().map(row_key).collect();
+
+                let event = (!items.is_empty()).then(|| PollEvent::Polled {
+                    station: station.clone(),
+                    items,
+                });
+
+                (event, config.interval)
+            }
+            Err(error) => {
+                failures += 1;
+                let retry_in = config.backoff(failures);
+
+                let event = PollEvent::Failed {
+                    station: station.clone(),
+                    error,
+                    retry_in,
+                };
+
+                (Some(event), retry_in)
+            }
+        };
+
+        if let Some(event) = event {
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stopped.changed() => {}
+        }
+    }
+}
+
+// Completes on the first SIGINT (Ctrl-C) or SIGTERM
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    const HEAD: &str = r#"<div id="playinc"><table><tbody>
+<tr><th>Played At</th><th>Artist</th><th>Song</th><th>Album</th><th></th></tr>
+<tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+"#;
+    const TAIL: &str = r#"<tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+</tbody></table></div>"#;
+
+    fn row(time: &str, artist: &str, song: &str) -> String {
+        format!(
+            r#"<tr><td>{}</td><td><a href="/buy/multibuy.cgi?mode=amazon">{}</a></td><td>{}</td><td><a href="/buy/multibuy.cgi?mode=amazon">Album</a></td><td></td></tr>"#,
+            time, artist, song
+        )
+    }
+
+    // Serves one page per fetch, then keeps serving the last one; None fails the fetch
+    struct SequenceFetcher {
+        pages: Mutex<VecDeque<Option<String>>>,
+    }
+
+    #[async_trait]
+    impl Fetcher for SequenceFetcher {
+        async fn try_fetch_element(&self, url: &str, _css: &str) -> Result<String> {
+            let mut pages = self.pages.lock().unwrap();
+            let page = match pages.len() {
+                1 => pages.front().cloned().flatten(),
+                _ => pages.pop_front().flatten(),
+            };
+
+            page.ok_or_else(|| {
+                SpiderError::fetch(
+                    url,
+                    std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset"),
+                )
+            })
+        }
+
+        async fn try_fetch_text(&self, url: &str) -> Result<String> {
+            self.try_fetch_element(url, "").await
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Collect {
+        items: Arc<Mutex<Vec<String>>>,
+        errors: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Sink for Collect {
+        fn try_send(&mut self, station: &Station, items: &[PlaylistItem]) -> Result<()> {
+            let mut collected = self.items.lock().unwrap();
+            collected.extend(
+                items
+                    .iter()
+                    .map(|item| format!("{} {}", station, item.artist())),
+            );
+
+            Ok(())
+        }
+
+        fn on_error(&mut self, _station: &Station, _error: &SpiderError, retry_in: Duration) {
+            self.errors.lock().unwrap().push(retry_in);
+        }
+    }
+
+    #[test]
+    fn test_01_backoff() {
+        let config = PollConfig {
+            interval: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(600),
+            mode: ScrapeMode::Strict,
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_secs(120));
+        assert_eq!(config.backoff(3), Duration::from_secs(480));
+        assert_eq!(config.backoff(4), Duration::from_secs(600));
+        assert_eq!(config.backoff(u32::MAX), Duration::from_secs(600));
+    }
+
+    #[tokio::test]
+    async fn test_02_poll_new_rows() {
+        let first = format!(
+            "{}{}{}{}",
+            HEAD,
+            row("14:21:19", "Afterlife", "Sun Dance"),
+            row("14:16:50", "Groove Matter", "Sun Dance"),
+            TAIL
+        );
+        let second = format!(
+            "{}{}{}{}{}",
+            HEAD,
+            row("14:26:00", "Sofa Lofa", "Magic Shopkeeper"),
+            row("14:21:19", "Afterlife", "Sun Dance"),
+            row("14:16:50", "Groove Matter", "Sun Dance"),
+            TAIL
+        );
+        let fetcher = SequenceFetcher {
+            pages: Mutex::new(VecDeque::from([Some(first), None, Some(second)])),
+        };
+        let config = PollConfig {
+            interval: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            mode: ScrapeMode::Strict,
+        };
+        let collect = Collect::default();
+
+        let poller = Poller::new(Arc::new(fetcher), &[Station::LUSH], config)
+            .with_sink(collect.clone())
+            .with_sink(ArchiveSink::new(Archive::in_memory()));
+
+        let items = collect.items.clone();
+        let shutdown = async move {
+            while items.lock().unwrap().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            // A few more polls of the same page must not repeat anything
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+
+        tokio::time::timeout(Duration::from_secs(5), poller.try_run(shutdown))
+            .await
+            .expect("poller did not shut down")
+            .unwrap();
+
+        assert_eq!(
+            *collect.items.lock().unwrap(),
+            vec!["lush Afterlife", "lush Groove Matter", "lush Sofa Lofa"]
+        );
+        assert_eq!(
+            *collect.errors.lock().unwrap(),
+            vec![Duration::from_millis(20)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_03_sink_error_stops() {
+        struct Broken;
+
+        impl Sink for Broken {
+            fn try_send(&mut self, _station: &Station, _items: &[PlaylistItem]) -> Result<()> {
+                Err(SpiderError::export(
+                    "broken",
+                    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "closed"),
+                ))
+            }
+        }
+
+        let page = format!(
+            "{}{}{}",
+            HEAD,
+            row("14:21:19", "Afterlife", "Sun Dance"),
+            TAIL
+        );
+        let fetcher = SequenceFetcher {
+            pages: Mutex::new(VecDeque::from([Some(page)])),
+        };
+        let collect = Collect::default();
+        let poller = Poller::new(
+            Arc::new(fetcher),
+            &[Station::LUSH, Station::DRONE_ZONE],
+            PollConfig {
+                interval: Duration::from_millis(10),
+                ..PollConfig::default()
+            },
+        )
+        .with_sink(collect.clone())
+        .with_sink(Broken);
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            poller.try_run(std::future::pending()),
+        )
+        .await
+        .expect("poller did not stop after the sink failed");
+
+        assert!(matches!(res, Err(SpiderError::Export { .. })));
+        // The other station's rows aren't sent anywhere after the failure
+        assert_eq!(collect.items.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_04_shutdown_mid_fetch() {
+        struct Hang;
+
+        #[async_trait]
+        impl Fetcher for Hang {
+            async fn try_fetch_element(&self, _url: &str, _css: &str) -> Result<String> {
+                std::future::pending().await
+            }
+
+            async fn try_fetch_text(&self, _url: &str) -> Result<String> {
+                std::future::pending().await
+            }
+        }
+
+        let poller = Poller::new(Arc::new(Hang), &[Station::LUSH], PollConfig::default())
+            .with_sink(Collect::default());
+
+        let shutdown = tokio::time::sleep(Duration::from_millis(20));
+        tokio::time::timeout(Duration::from_secs(5), poller.try_run(shutdown))
+            .await
+            .expect("poller waited for the fetch to finish")
+            .unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+/*
+    Send spider traffic through HTTP or SOCKS5 proxies (a VPN's SOCKS endpoint, Tor, ...) without
+    touching the OS network settings:
+
+        ProxyRoutes       which proxies each host goes through. A host uses its own list, or its
+                          parent domain's ("youtube.com" covers "www.youtube.com"), or else the
+                          default list. An empty list means a direct connection
+        ProxyRotation     takes turns over a host's list, and retires a proxy after
+                          `max_failures` failed requests in a row. `check_health()` tries to
+                          connect to every proxy, retiring dead ones and bringing back ones
+                          that answer again
+
+    HttpFetcher builds a reqwest client per proxy, and WebDriverFetcher a session pool per proxy
+    with the proxy in the browser's capabilities. When every proxy of a host is retired, its
+    fetches fail rather than quietly going out directly.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::rate;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+pub const MAX_FAILURES: u32 = 3;
+
+pub const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxyRoutes {
+    default: Vec<Url>,
+    hosts: HashMap<String, Vec<Url>>,
+}
+
+#[derive(Debug)]
+pub struct ProxyRotation {
+    routes: ProxyRoutes,
+    max_failures: u32,
+    // Failed requests in a row, per proxy
+    failures: Mutex<HashMap<Url, u32>>,
+    // Per route, where the next turn starts
+    next: Mutex<HashMap<String, usize>>,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+// "http://host:port", "https://host:port" or "socks5://host:port"
+pub fn try_parse_proxy(proxy: &str) -> Result<Url> {
+    let url = Url::parse(proxy).map_err(|e| SpiderError::parse("proxy", e))?;
+
+    if !matches!(url.scheme(), "http" | "https" | "socks5") || url.host_str().is_none() {
+        return Err(SpiderError::parse(
+            format!("proxy \"{}\"", proxy),
+            "expected http://host:port or socks5://host:port",
+        ));
+    }
+
+    Ok(url)
+}
+
+// Only checks that the proxy accepts connections, so it works the same for every scheme
+pub async fn is_reachable(proxy: &Url, timeout: Duration) -> bool {
+    let Some(host) = proxy.host_str() else {
+        return false;
+    };
+    // SOCKS has no default port in the url crate
+    let port = proxy.port_or_known_default().unwrap_or(1080);
+
+    let connect = tokio::net::TcpStream::connect((host, port));
+
+    matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_)))
+}
+
+impl ProxyRoutes {
+    pub fn new() -> Self {
+        ProxyRoutes::default()
+    }
+
+    // For every host without a route of its own
+    pub fn with_default(mut self, proxies: Vec<Url>) -> Self {
+        self.default = proxies;
+        self
+    }
+
+    // For `domain` and its subdomains; an empty list connects them directly
+    pub fn with_host(mut self, domain: &str, proxies: Vec<Url>) -> Self {
+        self.hosts.insert(domain.to_lowercase(), proxies);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_empty() && self.hosts.values().all(Vec::is_empty)
+    }
+
+    // The matching domain ("" for the default route) and its proxies
+    pub fn route(&self, host: &str) -> (&str, &[Url]) {
+        rate::match_domain(&self.hosts, host)
+            .map(|(domain, proxies)| (domain, proxies.as_slice()))
+            .unwrap_or(("", self.default.as_slice()))
+    }
+
+    // Every proxy in any route, once
+    pub fn proxies(&self) -> Vec<&Url> {
+        self.default
+            .iter()
+            .chain(self.hosts.values().flatten())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl ProxyRotation {
+    pub fn new(routes: ProxyRoutes) -> Self {
+        ProxyRotation {
+            routes,
+            max_failures: MAX_FAILURES,
+            failures: Mutex::new(HashMap::new()),
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // No proxies at all
+    pub fn direct() -> Self {
+        ProxyRotation::new(ProxyRoutes::new())
+    }
+
+    pub fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures.max(1);
+        self
+    }
+
+    pub fn routes(&self) -> &ProxyRoutes {
+        &self.routes
+    }
+
+    pub fn is_retired(&self, proxy: &Url) -> bool {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(proxy)
+            .is_some_and(|failures| *failures >= self.max_failures)
+    }
+
+    // The next live proxy for `url`'s host, or None to connect directly
+    pub fn try_pick(&self, url: &str) -> Result<Option<Url>> {
+        let host = rate::host(url);
+        let (route, proxies) = self.routes.route(host.as_str());
+
+        if proxies.is_empty() {
+            return Ok(None);
+        }
+
+        let failures = self.failures.lock().unwrap();
+        let mut next = self.next.lock().unwrap();
+        let start = next.entry(route.to_string()).or_default();
+
+        for i in 0..proxies.len() {
+            let index = (*start + i) % proxies.len();
+            let proxy = &proxies[index];
+
+            if failures.get(proxy).is_none_or(|f| *f < self.max_failures) {
+                *start = index + 1;
+                return Ok(Some(proxy.clone()));
+            }
+        }
+
+        Err(SpiderError::fetch(
+            url,
+            format!("all {} proxies for {} are down", proxies.len(), host),
+        ))
+    }
+
+    // Only failures the proxy could be behind count against it: timeouts, refused connections
+    // and the like, not a page that loaded without what we were looking for
+    pub fn record<T>(&self, proxy: Option<&Url>, res: &Result<T>) {
+        let Some(proxy) = proxy else {
+            return;
+        };
+
+        let mut failures = self.failures.lock().unwrap();
+        match res.as_ref().err().is_some_and(SpiderError::is_retryable) {
+            true => *failures.entry(proxy.clone()).or_default() += 1,
+            false => {
+                failures.remove(proxy);
+            }
+        }
+    }
+
+    // Retires the proxies that don't accept connections and brings back the ones that do.
+    // Returns the retired ones
+    pub async fn check_health(&self) -> Vec<Url> {
+        let mut res = vec![];
+
+        for proxy in self.routes.proxies() {
+            let reachable = is_reachable(proxy, HEALTH_TIMEOUT).await;
+
+            let mut failures = self.failures.lock().unwrap();
+            match reachable {
+                true => {
+                    failures.remove(proxy);
+                }
+                false => {
+                    failures.insert(proxy.clone(), self.max_failures);
+                    res.push(proxy.clone());
+                }
+            }
+        }
+
+        res
+    }
+
+    // Runs `check_health()` every `interval` for as long as anything else holds the rotation
+    pub fn spawn_health_checks(
+        self: &Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let rotation = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let Some(rotation) = rotation.upgrade() else {
+                    break;
+                };
+                rotation.check_health().await;
+            }
+        })
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YOUTUBE: &str = "https://www.youtube.com/results?search_query=afterlife";
+    const SOMAFM: &str = "https://somafm.com/groovesalad/songhistory.html";
+
+    fn proxies(urls: &[&str]) -> Vec<Url> {
+        urls.iter()
+            .map(|url| try_parse_proxy(url).unwrap())
+            .collect()
+    }
+
+    fn timeout(url: &str) -> Result<()> {
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+
+        Err(SpiderError::fetch(url, io))
+    }
+
+    #[test]
+    fn test_01_routes() {
+        let routes = ProxyRoutes::new()
+            .with_default(proxies(&["http://10.0.0.1:3128"]))
+            .with_host(
+                "youtube.com",
+                proxies(&["socks5://127.0.0.1:9050", "http://10.0.0.1:3128"]),
+            )
+            .with_host("somafm.com", vec![]);
+
+        assert_eq!(routes.route("www.youtube.com").0, "youtube.com");
+        assert_eq!(routes.route("www.youtube.com").1.len(), 2);
+        assert!(routes.route("somafm.com").1.is_empty());
+        assert_eq!(routes.route("example.com").0, "");
+        assert_eq!(routes.proxies().len(), 2);
+        assert!(ProxyRoutes::new()
+            .with_host("somafm.com", vec![])
+            .is_empty());
+
+        assert!(try_parse_proxy("ftp://10.0.0.1").is_err());
+        assert!(try_parse_proxy("proxy:3128").is_err());
+    }
+
+    #[test]
+    fn test_02_rotation() {
+        let list = proxies(&["http://10.0.0.1:3128", "http://10.0.0.2:3128"]);
+        let rotation = ProxyRotation::new(
+            ProxyRoutes::new()
+                .with_host("youtube.com", list.clone())
+                .with_host("somafm.com", vec![]),
+        )
+        .with_max_failures(2);
+
+        let picks = (0..4)
+            .map(|_| rotation.try_pick(YOUTUBE).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            picks,
+            vec![
+                list[0].clone(),
+                list[1].clone(),
+                list[0].clone(),
+                list[1].clone()
+            ]
+        );
+        assert_eq!(rotation.try_pick(SOMAFM).unwrap(), None);
+
+        // A missing element isn't the proxy's fault, a timeout may be
+        rotation.record(
+            Some(&list[0]),
+            &Err::<(), _>(SpiderError::missing_element(YOUTUBE, "#contents")),
+        );
+        rotation.record(Some(&list[0]), &timeout(YOUTUBE));
+        assert!(!rotation.is_retired(&list[0]));
+        rotation.record(Some(&list[0]), &timeout(YOUTUBE));
+        assert!(rotation.is_retired(&list[0]));
+
+        for _ in 0..3 {
+            assert_eq!(rotation.try_pick(YOUTUBE).unwrap(), Some(list[1].clone()));
+        }
+
+        // Never falls back to a direct connection
+        rotation.record(Some(&list[1]), &timeout(YOUTUBE));
+        rotation.record(Some(&list[1]), &timeout(YOUTUBE));
+        assert!(matches!(
+            rotation.try_pick(YOUTUBE),
+            Err(SpiderError::Fetch { .. })
+        ));
+
+        // One success is enough to bring a proxy back
+        rotation.record(Some(&list[1]), &Ok(()));
+        assert_eq!(rotation.try_pick(YOUTUBE).unwrap(), Some(list[1].clone()));
+    }
+
+    #[tokio::test]
+    async fn test_03_health() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = format!("http://{}", listener.local_addr().unwrap());
+
+        // Bind and drop, so nothing listens on the port any more
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead = format!("socks5://{}", closed.local_addr().unwrap());
+        drop(closed);
+
+        let list = proxies(&[live.as_str(), dead.as_str()]);
+        let rotation = ProxyRotation::new(ProxyRoutes::new().with_default(list.clone()));
+
+        // Retired by failed requests, but still answering
+        rotation.record(Some(&list[0]), &timeout(SOMAFM));
+        rotation.record(Some(&list[0]), &timeout(SOMAFM));
+        rotation.record(Some(&list[0]), &timeout(SOMAFM));
+        assert!(rotation.is_retired(&list[0]));
+
+        assert_eq!(rotation.check_health().await, vec![list[1].clone()]);
+        assert!(!rotation.is_retired(&list[0]));
+        assert!(rotation.is_retired(&list[1]));
+        assert_eq!(rotation.try_pick(SOMAFM).unwrap(), Some(list[0].clone()));
+    }
+}
@@ -0,0 +1,375 @@
+/*
+    Every page a spider fetches waits its turn here first, so we stay polite to the sites we
+    scrape. YouTube in particular starts blocking clients that search too fast.
+
+    Each host gets a token bucket: `burst` requests may go out back to back, and after that one
+    every `interval`. A request that has to wait also sleeps a random extra 0..`jitter`, so
+    requests don't arrive on an exact beat. A host uses its own limit, or its parent domain's
+    ("youtube.com" covers "www.youtube.com"). Any other host gets the default limit.
+
+    The fetchers share one process-wide limiter, `RateLimiter::global()`, unless they are handed
+    their own. Time comes from a `Clock`, so tests can use `ManualClock` and never really sleep.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    async fn sleep(&self, duration: Duration);
+}
+
+// The real time, through tokio
+pub struct SystemClock;
+
+// Starts at the moment it is created. `sleep` moves it forward and returns at once
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    sleeps: Mutex<Vec<Duration>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostLimit {
+    pub interval: Duration,
+    pub jitter: Duration,
+    pub burst: u32,
+}
+
+pub struct RateLimiter {
+    clock: Arc<dyn Clock>,
+    default: HostLimit,
+    hosts: HashMap<String, HostLimit>,
+    // Per bucket, when the next request would go out if nobody had used the burst. A request
+    // may go `burst` intervals before that
+    next: Mutex<HashMap<String, Instant>>,
+    rng: Mutex<fastrand::Rng>,
+}
+
+static GLOBAL: OnceLock<Arc<RateLimiter>> = OnceLock::new();
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+            sleeps: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    // Every `sleep` so far, in order
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleeps.lock().unwrap().push(duration);
+        self.advance(duration);
+    }
+}
+
+impl HostLimit {
+    // No limit at all
+    pub const NONE: HostLimit = HostLimit {
+        interval: Duration::ZERO,
+        jitter: Duration::ZERO,
+        burst: 1,
+    };
+
+    // One request per `interval`, no jitter
+    pub fn new(interval: Duration) -> Self {
+        HostLimit {
+            interval,
+            ..HostLimit::NONE
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+// "https://www.youtube.com/results?..." -> "www.youtube.com"; empty if there's no host
+pub(crate) fn host(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default()
+}
+
+// The longest domain in `domains` that is `host` or one of its parents
+pub(crate) fn match_domain<'a, T>(
+    domains: &'a HashMap<String, T>,
+    host: &str,
+) -> Option<(&'a str, &'a T)> {
+    let host = host.to_lowercase();
+
+    domains
+        .iter()
+        .filter(|(domain, _)| {
+            host == domain.as_str() || host.ends_with(format!(".{}", domain).as_str())
+        })
+        .max_by_key(|(domain, _)| domain.len())
+        .map(|(domain, value)| (domain.as_str(), value))
+}
+
+impl RateLimiter {
+    pub fn new(default: HostLimit) -> Self {
+        RateLimiter {
+            clock: Arc::new(SystemClock),
+            default,
+            hosts: HashMap::new(),
+            next: Mutex::new(HashMap::new()),
+            rng: Mutex::new(fastrand::Rng::new()),
+        }
+    }
+
+    // What the fetchers use unless told otherwise: the README's 10 seconds between YouTube
+    // searches, a little less for SomaFM, and a second between requests anywhere else
+    pub fn polite() -> Self {
+        RateLimiter::new(HostLimit::new(Duration::from_secs(1)))
+            .with_host(
+                "youtube.com",
+                HostLimit::new(Duration::from_secs(10)).with_jitter(Duration::from_secs(5)),
+            )
+            .with_host(
+                "somafm.com",
+                HostLimit::new(Duration::from_secs(2)).with_jitter(Duration::from_secs(1)),
+            )
+    }
+
+    // `polite()` until `try_set_global` installs something else
+    pub fn global() -> Arc<RateLimiter> {
+        GLOBAL
+            .get_or_init(|| Arc::new(RateLimiter::polite()))
+            .clone()
+    }
+
+    // Only works before the first fetcher is created, since those take the global limiter
+    pub fn try_set_global(limiter: RateLimiter) -> Result<()> {
+        GLOBAL
+            .set(Arc::new(limiter))
+            .map_err(|_| SpiderError::config("rate limits", "the global limiter is already in use"))
+    }
+
+    pub fn with_host(mut self, host: &str, limit: HostLimit) -> Self {
+        self.hosts.insert(host.to_lowercase(), limit);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Same jitter every run, for tests
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = fastrand::Rng::with_seed(seed);
+        self
+    }
+
+    // The most specific configured domain wins; the bucket is shared by all its subdomains
+    pub fn limit(&self, host: &str) -> (&str, HostLimit) {
+        match_domain(&self.hosts, host)
+            .map(|(domain, limit)| (domain, *limit))
+            .unwrap_or(("", self.default))
+    }
+
+    // Waits until a request to `url` may go out
+    pub async fn acquire(&self, url: &str) {
+        let wait = self.reserve(url);
+
+        if !wait.is_zero() {
+            self.clock.sleep(wait).await;
+        }
+    }
+
+    // Takes the next slot right away, so concurrent callers queue up behind each other
+    fn reserve(&self, url: &str) -> Duration {
+        let (bucket, limit) = self.limit(host(url).as_str());
+
+        if limit.interval.is_zero() {
+            return Duration::ZERO;
+        }
+
+        let now = self.clock.now();
+        let mut next = self.next.lock().unwrap();
+        let slot = next.get(bucket).map_or(now, |next| (*next).max(now));
+
+        let burst = limit.interval * (limit.burst - 1);
+        let wait = slot
+            .checked_sub(burst)
+            .map_or(Duration::ZERO, |at| at.saturating_duration_since(now));
+        let jitter = match wait.is_zero() {
+            true => Duration::ZERO,
+            false => self.jitter(limit.jitter),
+        };
+
+        // A late request pushes the ones behind it back too, so jitter never shortens a gap
+        next.insert(bucket.to_string(), slot + jitter + limit.interval);
+
+        wait + jitter
+    }
+
+    fn jitter(&self, max: Duration) -> Duration {
+        if max.is_zero() {
+            return max;
+        }
+
+        let nanos = self.rng.lock().unwrap().u64(0..=max.as_nanos() as u64);
+
+        Duration::from_nanos(nanos)
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YOUTUBE: &str = "https://www.youtube.com/results?search_query=afterlife";
+    const SOMAFM: &str = "https://somafm.com/groovesalad/songhistory.html";
+
+    fn limiter(clock: &Arc<ManualClock>) -> RateLimiter {
+        RateLimiter::new(HostLimit::NONE)
+            .with_host("youtube.com", HostLimit::new(Duration::from_secs(10)))
+            .with_host(
+                "somafm.com",
+                HostLimit::new(Duration::from_secs(2)).with_burst(3),
+            )
+            .with_clock(clock.clone())
+    }
+
+    #[test]
+    fn test_01_limit() {
+        let limiter = RateLimiter::polite().with_host("music.youtube.com", HostLimit::NONE);
+
+        assert_eq!(limiter.limit("www.youtube.com").0, "youtube.com");
+        assert_eq!(limiter.limit("YouTube.com").0, "youtube.com");
+        assert_eq!(limiter.limit("music.youtube.com").1, HostLimit::NONE);
+        assert_eq!(limiter.limit("notyoutube.com").0, "");
+        assert_eq!(
+            limiter.limit("example.com").1,
+            HostLimit::new(Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_02_interval() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = limiter(&clock);
+
+        for _ in 0..3 {
+            limiter.acquire(YOUTUBE).await;
+        }
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_secs(10), Duration::from_secs(10)]
+        );
+
+        // Time spent elsewhere counts towards the wait
+        clock.advance(Duration::from_secs(4));
+        limiter.acquire("https://m.youtube.com/").await;
+        assert_eq!(clock.sleeps()[2], Duration::from_secs(6));
+
+        // Hosts without a limit never wait
+        limiter.acquire("https://example.com/").await;
+        assert_eq!(clock.sleeps().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_03_burst() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = limiter(&clock);
+
+        for _ in 0..4 {
+            limiter.acquire(SOMAFM).await;
+        }
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(2)]);
+
+        // A quiet spell refills the bucket, but never past `burst`
+        clock.advance(Duration::from_secs(60));
+        for _ in 0..4 {
+            limiter.acquire(SOMAFM).await;
+        }
+        assert_eq!(clock.sleeps().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_04_jitter() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = RateLimiter::new(
+            HostLimit::new(Duration::from_secs(10)).with_jitter(Duration::from_secs(5)),
+        )
+        .with_clock(clock.clone())
+        .with_seed(7);
+
+        for _ in 0..20 {
+            limiter.acquire(YOUTUBE).await;
+        }
+
+        let sleeps = clock.sleeps();
+        assert_eq!(sleeps.len(), 19);
+        assert!(sleeps
+            .iter()
+            .all(|d| *d >= Duration::from_secs(10) && *d <= Duration::from_secs(15)));
+        assert!(sleeps.windows(2).any(|w| w[0] != w[1]));
+
+        // Jitter comes on top of the interval, so it never makes the next wait shorter
+        assert!(clock.elapsed() >= Duration::from_secs(190));
+    }
+}
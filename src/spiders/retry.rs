@@ -0,0 +1,395 @@
+/*
+    One dropped connection or slow page shouldn't fail a whole scrape, so `RetryFetcher` wraps
+    any other fetcher:
+
+        RetryPolicy       how often to try, how long to back off between tries, and when to give
+                          up on a host altogether
+        RetryFetcher      retries what `SpiderError::is_retryable` says is worth it (timeouts,
+                          5xx, stale elements, elements the browser gave up waiting for) and
+                          returns anything else straight away, like a selector that isn't on a
+                          page that did load
+
+    Each host has a circuit breaker. After `breaker_threshold` failed tries in a row it opens, and
+    every fetch from that host fails at once with `CircuitOpen` for `breaker_cooldown`. After that
+    one fetch is let through while the others keep failing; if it fails too, the breaker opens
+    again, and if it never reports back, the next one goes through after another cooldown.
+
+    The wrapped fetcher still rate limits every try, so retries stay polite as well.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::fetch::{Fetched, Fetcher};
+use crate::spiders::rate::{self, Clock, SystemClock};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    // Failed tries in a row
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+pub struct RetryFetcher<F> {
+    inner: F,
+    policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(30),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Try once and never open the breaker
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+        breaker_threshold: u32::MAX,
+        breaker_cooldown: Duration::ZERO,
+    };
+
+    // How long to wait after the `attempt`th try failed: base_delay * 2^(attempt - 1)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(16));
+
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+impl<F: Fetcher> RetryFetcher<F> {
+    pub fn new(inner: F, policy: RetryPolicy) -> Self {
+        RetryFetcher {
+            inner,
+            policy,
+            clock: Arc::new(SystemClock),
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn is_open(&self, host: &str) -> bool {
+        let now = self.clock.now();
+
+        self.breakers
+            .lock()
+            .unwrap()
+            .get(host)
+            .and_then(|breaker| breaker.open_until)
+            .is_some_and(|until| now < until)
+    }
+
+    // Fails with `CircuitOpen` while the breaker is open; once the cooldown is over, lets one
+    // try through and reopens on its first failure
+    fn try_check(&self, host: &str) -> Result<()> {
+        let now = self.clock.now();
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(host.to_string()).or_default();
+
+        match breaker.open_until {
+            Some(until) if now < until => Err(SpiderError::circuit_open(host, until - now)),
+            Some(_) => {
+                // Still open to everyone else until this try succeeds
+                breaker.open_until = Some(now + self.policy.breaker_cooldown);
+                breaker.failures = self.policy.breaker_threshold.saturating_sub(1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // True when this failure opened the breaker
+    fn record(&self, host: &str, failed: bool) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(host.to_string()).or_default();
+
+        if !failed {
+            *breaker = Breaker::default();
+            return false;
+        }
+
+        breaker.failures += 1;
+        if breaker.failures < self.policy.breaker_threshold {
+            return false;
+        }
+
+        breaker.open_until = Some(self.clock.now() + self.policy.breaker_cooldown);
+        true
+    }
+
+    async fn try_with_retry<T, Fut>(&self, url: &str, fetch: impl Fn() -> Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        let host = rate::host(url);
+        let mut attempt = 0;
+
+        loop {
+            self.try_check(host.as_str())?;
+            attempt += 1;
+
+            let e = match fetch().await {
+                Ok(res) => {
+                    self.record(host.as_str(), false);
+                    return Ok(res);
+                }
+                Err(e) => e,
+            };
+
+            // The site answered, it just didn't have what we wanted
+            if !e.is_retryable() {
+                self.record(host.as_str(), false);
+                return Err(e);
+            }
+
+            let opened = self.record(host.as_str(), true);
+            if opened || attempt >= self.policy.max_attempts {
+                return Err(e);
+            }
+
+            self.clock.sleep(self.policy.backoff(attempt)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<F: Fetcher> Fetcher for RetryFetcher<F> {
+    async fn try_fetch_element(&self, url: &str, css: &str) -> Result<String> {
+        self.try_with_retry(url, || self.inner.try_fetch_element(url, css))
+            .await
+    }
+
+    async fn try_fetch_element_dated(&self, url: &str, css: &str) -> Result<Fetched> {
+        self.try_with_retry(url, || self.inner.try_fetch_element_dated(url, css))
+            .await
+    }
+
+    async fn try_fetch_text(&self, url: &str) -> Result<String> {
+        self.try_with_retry(url, || self.inner.try_fetch_text(url))
+            .await
+    }
+
+    async fn try_close(&self) -> Result<()> {
+        self.inner.try_close().await
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::rate::ManualClock;
+    use std::collections::VecDeque;
+
+    const SOMAFM: &str = "https://somafm.com/groovesalad/songhistory.html";
+
+    // Answers with the scripted results in order, then keeps failing
+    struct FlakyFetcher {
+        results: Mutex<VecDeque<Result<String>>>,
+        calls: Mutex<u32>,
+    }
+
+    impl FlakyFetcher {
+        fn new(results: Vec<Result<String>>) -> Self {
+            FlakyFetcher {
+                results: Mutex::new(results.into()),
+                calls: Mutex::new(0),
+            }
+        }
+
+        fn calls(&self) -> u32 {
+            *self.calls.lock().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl Fetcher for FlakyFetcher {
+        async fn try_fetch_element(&self, url: &str, _css: &str) -> Result<String> {
+            self.try_fetch_text(url).await
+        }
+
+        async fn try_fetch_text(&self, url: &str) -> Result<String> {
+            *self.calls.lock().unwrap() += 1;
+
+            self.results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| Err(timeout(url)))
+        }
+    }
+
+    fn timeout(url: &str) -> SpiderError {
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+
+        SpiderError::fetch(url, io)
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            breaker_threshold: 4,
+            breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+
+    fn retry_fetcher(
+        results: Vec<Result<String>>,
+    ) -> (RetryFetcher<FlakyFetcher>, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let res = RetryFetcher::new(FlakyFetcher::new(results), policy()).with_clock(clock.clone());
+
+        (res, clock)
+    }
+
+    #[test]
+    fn test_01_backoff() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(5), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_02_retry() {
+        let (fetcher, clock) = retry_fetcher(vec![
+            Err(timeout(SOMAFM)),
+            Err(timeout(SOMAFM)),
+            Ok("<table></table>".to_string()),
+        ]);
+
+        let res = fetcher.try_fetch_element(SOMAFM, "table").await.unwrap();
+
+        assert_eq!(res, "<table></table>");
+        assert_eq!(fetcher.inner().calls(), 3);
+        assert_eq!(
+            clock.sleeps(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+
+        // Out of attempts: the last error comes back as is
+        let res = fetcher.try_fetch_text(SOMAFM).await;
+        assert!(matches!(res, Err(SpiderError::Fetch { .. })));
+        assert_eq!(fetcher.inner().calls(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_03_fatal() {
+        let (fetcher, clock) =
+            retry_fetcher(vec![Err(SpiderError::missing_element(SOMAFM, "#playinc"))]);
+
+        let res = fetcher.try_fetch_element(SOMAFM, "#playinc").await;
+
+        assert!(matches!(res, Err(SpiderError::MissingElement { .. })));
+        assert_eq!(fetcher.inner().calls(), 1);
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_04_breaker() {
+        let (fetcher, clock) = retry_fetcher(vec![]);
+
+        // 3 failed tries, then the 4th opens the breaker halfway through the second fetch
+        assert!(fetcher.try_fetch_text(SOMAFM).await.is_err());
+        assert!(fetcher.try_fetch_text(SOMAFM).await.is_err());
+        assert_eq!(fetcher.inner().calls(), 4);
+        assert!(fetcher.is_open("somafm.com"));
+
+        let res = fetcher.try_fetch_text(SOMAFM).await;
+        assert!(matches!(
+            res,
+            Err(SpiderError::CircuitOpen { ref host, .. }) if host == "somafm.com"
+        ));
+        assert_eq!(fetcher.inner().calls(), 4);
+
+        // Other hosts aren't affected
+        assert!(fetcher
+            .try_fetch_text("https://www.youtube.com/")
+            .await
+            .is_err());
+        assert!(!fetcher.is_open("www.youtube.com"));
+
+        // After the cooldown one try goes through, and its failure reopens the breaker
+        clock.advance(Duration::from_secs(60));
+        let calls = fetcher.inner().calls();
+        assert!(matches!(
+            fetcher.try_fetch_text(SOMAFM).await,
+            Err(SpiderError::Fetch { .. })
+        ));
+        assert_eq!(fetcher.inner().calls(), calls + 1);
+        assert!(fetcher.is_open("somafm.com"));
+    }
+
+    #[tokio::test]
+    async fn test_05_single_probe() {
+        let (fetcher, clock) = retry_fetcher(vec![]);
+        assert!(fetcher.try_fetch_text(SOMAFM).await.is_err());
+        assert!(fetcher.try_fetch_text(SOMAFM).await.is_err());
+        assert!(fetcher.is_open("somafm.com"));
+
+        // After the cooldown only the first caller gets through, however many are waiting
+        clock.advance(Duration::from_secs(60));
+        assert!(fetcher.try_check("somafm.com").is_ok());
+        assert!(matches!(
+            fetcher.try_check("somafm.com"),
+            Err(SpiderError::CircuitOpen { .. })
+        ));
+
+        // Its success closes the breaker for everyone
+        fetcher.record("somafm.com", false);
+        assert!(!fetcher.is_open("somafm.com"));
+        assert!(fetcher.try_check("somafm.com").is_ok());
+    }
+}
@@ -0,0 +1,345 @@
+/*
+    The first search hit is often a cover, a live recording or somebody's reaction video, so
+    `MatchScorer` rates every candidate against the song instead, from 0 to 1:
+
+        title       0.45    how many of the song's words are in the video title. Words match
+                            fuzzily, so "Avenida" still finds "Avenid"
+        artist      0.25    the artist's words in the channel name, or (worth a bit less) in the
+                            video title
+        duration    0.15    a plausible song length, 1 to 15 minutes; half marks when unknown
+        channel     0.15    an "Official" / VEVO / "- Topic" channel, or "Official" in the title
+
+    Every word from PENALTIES that is in the title but not in the song ("live", "cover", ...)
+    takes 0.3 off. `best()` returns the highest scoring candidate, the earlier one on a tie, and
+    nothing when even that scores below the threshold. `BestMatch` resolves songs that way, and
+    fails with `Unresolved` for songs without a good enough match.
+*/
+
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::normalize::NormalizedItem;
+use crate::spiders::somafm::PlaylistItem;
+use crate::spiders::youtube::{self, Resolve, TupleHash, VideoCandidate};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+pub const THRESHOLD: f64 = 0.5;
+
+pub const PENALTIES: &[&str] = &[
+    "live",
+    "cover",
+    "karaoke",
+    "reaction",
+    "reacts",
+    "instrumental",
+];
+
+const TITLE_WEIGHT: f64 = 0.45;
+const ARTIST_WEIGHT: f64 = 0.25;
+const DURATION_WEIGHT: f64 = 0.15;
+const CHANNEL_WEIGHT: f64 = 0.15;
+const PENALTY: f64 = 0.3;
+
+// Two words this similar (Dice coefficient of their letter pairs) count as the same word
+const FUZZY_WORD: f64 = 0.75;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchScorer {
+    threshold: f64,
+    min_duration: Duration,
+    max_duration: Duration,
+}
+
+pub struct BestMatch<'a> {
+    scorer: &'a MatchScorer,
+    results: &'a TupleHash<String, Vec<VideoCandidate>>,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+// "Groove Matter - 97 Ways (Official)" -> ["groove", "matter", "97", "ways", "official"]
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn pairs(word: &str) -> Vec<(char, char)> {
+    let chars = word.chars().collect::<Vec<_>>();
+
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+// Sørensen–Dice coefficient of the letter pairs: 1 for the same word, 0 for nothing in common
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+
+    let a = pairs(a);
+    let mut b = pairs(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let total = (a.len() + b.len()) as f64;
+    let mut common = 0;
+    for pair in &a {
+        if let Some(index) = b.iter().position(|other| other == pair) {
+            b.swap_remove(index);
+            common += 1;
+        }
+    }
+
+    2.0 * common as f64 / total
+}
+
+// The share of `wanted` that is somewhere in `found`
+fn coverage(wanted: &[String], found: &[String]) -> f64 {
+    if wanted.is_empty() {
+        return 0.0;
+    }
+
+    let hits = wanted
+        .iter()
+        .filter(|word| {
+            found
+                .iter()
+                .any(|other| similarity(word, other) >= FUZZY_WORD)
+        })
+        .count();
+
+    hits as f64 / wanted.len() as f64
+}
+
+impl Default for MatchScorer {
+    fn default() -> Self {
+        MatchScorer {
+            threshold: THRESHOLD,
+            min_duration: Duration::from_secs(60),
+            max_duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl MatchScorer {
+    pub fn new() -> Self {
+        MatchScorer::default()
+    }
+
+    // From 0 to 1; 0 takes the best candidate whatever it is
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    // What a song may last; videos outside it are likely mixes or clips
+    pub fn with_durations(mut self, min: Duration, max: Duration) -> Self {
+        self.min_duration = min;
+        self.max_duration = max;
+        self
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn score(&self, item: &PlaylistItem, candidate: &VideoCandidate) -> f64 {
+        let normalized = NormalizedItem::from(item);
+        let song = words(normalized.song());
+        let artist = words(normalized.artist());
+        let title = words(candidate.title());
+        let channel = words(candidate.channel());
+
+        let title_score = coverage(&song, &title);
+        let artist_score = coverage(&artist, &channel).max(0.8 * coverage(&artist, &title));
+
+        let duration_score = match candidate.duration() {
+            Some(duration) if duration >= self.min_duration && duration <= self.max_duration => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+
+        let channel_name = candidate.channel().to_lowercase();
+        let is_official = channel_name.ends_with("- topic")
+            || channel_name.contains("official")
+            || channel_name.contains("vevo")
+            || title.iter().any(|word| word == "official");
+        let channel_score = match is_official {
+            true => 1.0,
+            false => 0.0,
+        };
+
+        // "Live Forever" by itself is no reason to pass on a video
+        let song = song.iter().collect::<HashSet<_>>();
+        let penalties = PENALTIES
+            .iter()
+            .filter(|penalty| {
+                title.iter().any(|word| word == *penalty) && !song.contains(&penalty.to_string())
+            })
+            .count();
+
+        let res = TITLE_WEIGHT * title_score
+            + ARTIST_WEIGHT * artist_score
+            + DURATION_WEIGHT * duration_score
+            + CHANNEL_WEIGHT * channel_score
+            - PENALTY * penalties as f64;
+
+        res.clamp(0.0, 1.0)
+    }
+
+    // The best candidate and its score, if it reaches the threshold
+    pub fn best<'a>(
+        &self,
+        item: &PlaylistItem,
+        candidates: &'a [VideoCandidate],
+    ) -> Option<(&'a VideoCandidate, f64)> {
+        candidates
+            .iter()
+            .map(|candidate| (candidate, self.score(item, candidate)))
+            .fold(
+                None,
+                |best: Option<(&VideoCandidate, f64)>, (candidate, score)| match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((candidate, score)),
+                },
+            )
+            .filter(|(_, score)| *score >= self.threshold)
+    }
+}
+
+impl<'a> BestMatch<'a> {
+    pub fn new(
+        scorer: &'a MatchScorer,
+        results: &'a TupleHash<String, Vec<VideoCandidate>>,
+    ) -> Self {
+        BestMatch { scorer, results }
+    }
+}
+
+// Resolve from search results keyed by `search_terms()`, taking the best match
+impl Resolve for BestMatch<'_> {
+    fn try_resolve(&self, item: &PlaylistItem) -> Result<String> {
+        self.scorer
+            .best(item, self.results.candidates(item))
+            .map(|(candidate, _)| candidate.video_url())
+            .ok_or_else(|| SpiderError::Unresolved {
+                query: youtube::search_terms(item),
+            })
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::somafm::{test_played_at, test_song};
+    use std::collections::HashMap;
+
+    // A reaction video first, then a live version, a mix, and the song on its Topic channel
+    const SEARCH_HTML: &str = r#"
+<ytd-video-renderer>
+  <a id="video-title" title="Groove Matter - 97 Ways REACTION!!" href="/watch?v=reactReact1"></a>
+  <ytd-channel-name><a href="/@reacts">Ray Reacts</a></ytd-channel-name>
+</ytd-video-renderer>
+<ytd-video-renderer>
+  <a id="video-title" title="97 Ways (Live at Mezzanine)" href="/watch?v=zzTopLive01"></a>
+  <ytd-channel-name><a href="/@groovematter">Groove Matter</a></ytd-channel-name>
+</ytd-video-renderer>
+<ytd-video-renderer>
+  <ytd-thumbnail-overlay-time-status-renderer><span id="text">1:02:03</span></ytd-thumbnail-overlay-time-status-renderer>
+  <a id="video-title" title="Groove Salad Mix &amp; Chill - 1 Hour" href="/watch?v=Qm4bXo2vL0c"></a>
+  <ytd-channel-name><a href="/@chillmixes">Chill Mixes</a></ytd-channel-name>
+</ytd-video-renderer>
+<ytd-video-renderer>
+  <ytd-thumbnail-overlay-time-status-renderer><span id="text">6:12</span></ytd-thumbnail-overlay-time-status-renderer>
+  <a id="video-title" title="97 Ways" href="/watch?v=8HY3yD0sA8Y"></a>
+  <ytd-channel-name><a href="/@groovematter-topic">Groove Matter - Topic</a></ytd-channel-name>
+</ytd-video-renderer>
+"#;
+
+    #[test]
+    fn test_01_similarity() {
+        assert_eq!(similarity("ways", "ways"), 1.0);
+        assert_eq!(similarity("ways", "moon"), 0.0);
+        assert!(similarity("avenida", "avenid") >= FUZZY_WORD);
+        assert!(similarity("night", "nacht") < FUZZY_WORD);
+
+        assert_eq!(
+            words("Groove Matter - 97 Ways!"),
+            vec!["groove", "matter", "97", "ways"]
+        );
+        assert_eq!(
+            coverage(&words("5th & Avenida"), &words("Afterlife - 5th Avenid")),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_02_best() {
+        let item = test_song(test_played_at(20, 55, 1), "Groove Matter", "97 Ways");
+        let candidates = youtube::try_scrape_search_html(SEARCH_HTML).unwrap();
+        let scorer = MatchScorer::new();
+
+        let scores = candidates
+            .iter()
+            .map(|candidate| scorer.score(&item, candidate))
+            .collect::<Vec<_>>();
+        println!("Scores: {:?}", scores);
+
+        let (best, score) = scorer.best(&item, &candidates).unwrap();
+        assert_eq!(best.video_id(), "8HY3yD0sA8Y");
+        assert!(score > 0.99);
+
+        // The reaction and live videos lose to the Topic channel, and the mix to everything
+        assert!(scores[0] < THRESHOLD);
+        assert!(scores[1] < THRESHOLD);
+        assert!(scores[2] < scores[0].min(scores[1]));
+
+        // ...unless the song is the live version
+        let live = test_song(test_played_at(20, 55, 1), "Groove Matter", "97 Ways (Live)");
+        assert!(scorer.score(&live, &candidates[1]) > scores[1]);
+    }
+
+    #[test]
+    fn test_03_threshold() {
+        let candidates = youtube::try_scrape_search_html(SEARCH_HTML).unwrap();
+        let playlist = [
+            test_song(test_played_at(20, 55, 1), "Groove Matter", "97 Ways"),
+            test_song(test_played_at(20, 55, 1), "Afterlife", "5th & Avenida"),
+        ];
+        let results = TupleHash::from(
+            playlist
+                .iter()
+                .map(|item| (youtube::search_terms(item), candidates.clone()))
+                .collect::<HashMap<_, _>>(),
+        );
+
+        let scorer = MatchScorer::new();
+        let resolver = BestMatch::new(&scorer, &results);
+        assert_eq!(
+            resolver.try_resolve(&playlist[0]).unwrap(),
+            "https://www.youtube.com/watch?v=8HY3yD0sA8Y"
+        );
+        // Nothing on the page is that song, so no video rather than a wrong one
+        assert!(matches!(
+            resolver.try_resolve(&playlist[1]),
+            Err(SpiderError::Unresolved { ref query }) if query == "Afterlife + 5th & Avenida"
+        ));
+
+        let lenient = MatchScorer::new().with_threshold(0.0);
+        assert!(BestMatch::new(&lenient, &results)
+            .try_resolve(&playlist[1])
+            .is_ok());
+    }
+}
@@ -0,0 +1,598 @@
+/*
+    Starting a WebDriver session launches a whole browser, so keep a few open and lend them out:
+
+        Pool<M>           at most `max_size` sessions; idle ones are health-checked before they
+                          are handed out again, and dead ones are replaced by a fresh connection
+        Manager           how to open, check and close a session -- `WebDriverManager` for
+                          fantoccini, anything else in tests
+        Capabilities      browser, headless, user agent, window size, proxy and page load
+                          timeout. With no browser picked, options go out for Chrome and
+                          Firefox alike and the driver uses whichever it runs
+
+    Borrow with `try_get()`; the session goes back to the pool when the guard is dropped, unless
+    it was `discard()`ed after an error. `try_close()` at shutdown waits for every session to come
+    back and closes them all.
+*/
+
+use crate::spiders::config::{DriverConfig, TlsBackend};
+use crate::spiders::error::{Result, SpiderError};
+use crate::spiders::proxy;
+use async_trait::async_trait;
+use fantoccini::ClientBuilder;
+use serde_json::{json, Value};
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/********************************************************************************************
+ * Datastructure
+********************************************************************************************/
+
+#[async_trait]
+pub trait Manager: Send + Sync + 'static {
+    type Session: Send + 'static;
+
+    async fn try_connect(&self) -> Result<Self::Session>;
+
+    // Cheap round trip to the session; false means it should be replaced
+    async fn is_healthy(&self, session: &Self::Session) -> bool;
+
+    async fn try_close(&self, session: Self::Session) -> Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Browser {
+    Chrome,
+    Firefox,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    browser: Option<Browser>,
+    headless: bool,
+    user_agent: Option<String>,
+    window_size: Option<(u32, u32)>,
+    proxy: Option<url::Url>,
+    page_load_timeout: Option<Duration>,
+}
+
+pub struct WebDriverManager {
+    webdriver: String,
+    tls: TlsBackend,
+    capabilities: Capabilities,
+}
+
+pub struct Pool<M: Manager> {
+    manager: M,
+    idle: Mutex<Vec<M::Session>>,
+    // One permit per session, idle or lent out
+    permits: Arc<Semaphore>,
+    max_size: usize,
+}
+
+pub type SessionPool = Pool<WebDriverManager>;
+
+pub struct PooledSession<M: Manager> {
+    pool: Arc<Pool<M>>,
+    session: Option<M::Session>,
+    _permit: OwnedSemaphorePermit,
+}
+
+/********************************************************************************************
+ * Methods
+********************************************************************************************/
+
+impl std::fmt::Display for Browser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Browser::Chrome => f.write_str("chrome"),
+            Browser::Firefox => f.write_str("firefox"),
+        }
+    }
+}
+
+impl FromStr for Browser {
+    type Err = SpiderError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chrome" => Ok(Browser::Chrome),
+            "firefox" => Ok(Browser::Firefox),
+            _ => Err(SpiderError::parse(
+                format!("browser \"{}\"", name),
+                "expected \"chrome\" or \"firefox\"",
+            )),
+        }
+    }
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Capabilities::default()
+    }
+
+    pub fn with_browser(mut self, browser: Option<Browser>) -> Self {
+        self.browser = browser;
+        self
+    }
+
+    pub fn with_headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    pub fn with_window_size(mut self, width: u32, height: u32) -> Self {
+        self.window_size = Some((width, height));
+        self
+    }
+
+    // "http://host:port" or "socks5://host:port"
+    pub fn try_with_proxy(self, proxy: &str) -> Result<Self> {
+        Ok(self.with_proxy(Some(proxy::try_parse_proxy(proxy)?)))
+    }
+
+    pub fn with_proxy(mut self, proxy: Option<url::Url>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn with_page_load_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.page_load_timeout = timeout;
+        self
+    }
+
+    pub fn browser(&self) -> Option<Browser> {
+        self.browser
+    }
+
+    pub fn headless(&self) -> bool {
+        self.headless
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    pub fn window_size(&self) -> Option<(u32, u32)> {
+        self.window_size
+    }
+
+    pub fn proxy(&self) -> Option<&url::Url> {
+        self.proxy.as_ref()
+    }
+
+    pub fn page_load_timeout(&self) -> Option<Duration> {
+        self.page_load_timeout
+    }
+
+    // Options for both drivers; each ignores the other's
+    pub fn to_webdriver(&self) -> fantoccini::wd::Capabilities {
+        let mut chrome = Vec::<String>::new();
+        let mut firefox = Vec::<String>::new();
+        let mut firefox_prefs = serde_json::Map::new();
+
+        if self.headless {
+            chrome.push("--headless".to_string());
+            firefox.push("-headless".to_string());
+        }
+        if let Some(user_agent) = &self.user_agent {
+            chrome.push(format!("--user-agent={}", user_agent));
+            firefox_prefs.insert(
+                "general.useragent.override".to_string(),
+                Value::from(user_agent.as_str()),
+            );
+        }
+        if let Some((width, height)) = self.window_size {
+            chrome.push(format!("--window-size={},{}", width, height));
+            firefox.push(format!("--width={}", width));
+            firefox.push(format!("--height={}", height));
+        }
+
+        let mut res = fantoccini::wd::Capabilities::new();
+        if let Some(browser) = self.browser {
+            res.insert("browserName".to_string(), Value::from(browser.to_string()));
+        }
+        if self.browser != Some(Browser::Firefox) {
+            res.insert("goog:chromeOptions".to_string(), json!({ "args": chrome }));
+        }
+        if self.browser != Some(Browser::Chrome) {
+            res.insert(
+                "moz:firefoxOptions".to_string(),
+                json!({ "args": firefox, "prefs": firefox_prefs }),
+            );
+        }
+        if let Some(proxy) = &self.proxy {
+            res.insert("proxy".to_string(), proxy_capability(proxy));
+        }
+        if let Some(timeout) = self.page_load_timeout {
+            res.insert(
+                "timeouts".to_string(),
+                json!({ "pageLoad": timeout.as_millis() as u64 }),
+            );
+        }
+
+        res
+    }
+}
+
+// W3C proxy capability; it wants "host:port" without the scheme
+fn proxy_capability(proxy: &url::Url) -> Value {
+    let host = match (proxy.host_str(), proxy.port_or_known_default()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::new(),
+    };
+
+    match proxy.scheme() {
+        "socks5" => json!({
+            "proxyType": "manual",
+            "socksProxy": host,
+            "socksVersion": 5,
+        }),
+        _ => json!({
+            "proxyType": "manual",
+            "httpProxy": host,
+            "sslProxy": host,
+        }),
+    }
+}
+
+impl WebDriverManager {
+    pub fn new(config: &DriverConfig) -> Self {
+        WebDriverManager {
+            webdriver: config.endpoint().to_string(),
+            tls: config.tls(),
+            capabilities: config.capabilities().clone(),
+        }
+    }
+
+    pub fn webdriver(&self) -> &str {
+        &self.webdriver
+    }
+}
+
+#[async_trait]
+impl Manager for WebDriverManager {
+    type Session = fantoccini::Client;
+
+    async fn try_connect(&self) -> Result<Self::Session> {
+        let capabilities = self.capabilities.to_webdriver();
+
+        let c = match self.tls {
+            TlsBackend::Rustls => {
+                ClientBuilder::rustls()
+                    .capabilities(capabilities)
+                    .connect(&self.webdriver)
+                    .await
+            }
+            TlsBackend::Native => {
+                ClientBuilder::native()
+                    .capabilities(capabilities)
+                    .connect(&self.webdriver)
+                    .await
+            }
+        }
+        .map_err(|e| SpiderError::fetch(&self.webdriver, e))?;
+
+        Ok(c)
+    }
+
+    async fn is_healthy(&self, session: &Self::Session) -> bool {
+        session.current_url().await.is_ok()
+    }
+
+    async fn try_close(&self, session: Self::Session) -> Result<()> {
+        session
+            .close()
+            .await
+            .map_err(|e| SpiderError::fetch(&self.webdriver, e))
+    }
+}
+
+impl SessionPool {
+    pub fn from_config(config: &DriverConfig) -> Arc<Self> {
+        Pool::new(WebDriverManager::new(config), config.sessions())
+    }
+}
+
+impl<M: Manager> Pool<M> {
+    pub fn new(manager: M, max_size: usize) -> Arc<Self> {
+        let max_size = max_size.max(1);
+
+        Arc::new(Pool {
+            manager,
+            idle: Mutex::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(max_size)),
+            max_size,
+        })
+    }
+
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    // Waits while all `max_size` sessions are lent out
+    pub async fn try_get(self: &Arc<Self>) -> Result<PooledSession<M>> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the pool never closes its semaphore");
+
+        loop {
+            let idle = self.idle.lock().unwrap().pop();
+            let Some(session) = idle else {
+                break;
+            };
+
+            if self.manager.is_healthy(&session).await {
+                return Ok(self.lend(session, permit));
+            }
+
+            // Dead already, so a failed close tells us nothing
+            let _ = self.manager.try_close(session).await;
+        }
+
+        let session = self.manager.try_connect().await?;
+
+        Ok(self.lend(session, permit))
+    }
+
+    fn lend(
+        self: &Arc<Self>,
+        session: M::Session,
+        permit: OwnedSemaphorePermit,
+    ) -> PooledSession<M> {
+        PooledSession {
+            pool: self.clone(),
+            session: Some(session),
+            _permit: permit,
+        }
+    }
+
+    // Closes only the idle sessions; ones lent out go back to the pool still open. Use `try_close`
+    // to close those as well
+    pub async fn try_close_idle(&self) -> Result<()> {
+        let idle = std::mem::take(&mut *self.idle.lock().unwrap());
+
+        let mut res = Ok(());
+        for session in idle {
+            if let Err(e) = self.manager.try_close(session).await {
+                res = res.and(Err(e));
+            }
+        }
+
+        res
+    }
+
+    // Closes every session, waiting for the ones lent out to come back first. The pool can still
+    // be borrowed from afterwards, with fresh sessions
+    pub async fn try_close(&self) -> Result<()> {
+        let _all = self
+            .permits
+            .acquire_many(self.max_size as u32)
+            .await
+            .expect("the pool never closes its semaphore");
+
+        self.try_close_idle().await
+    }
+}
+
+impl<M: Manager> PooledSession<M> {
+    // Don't give this session back; the next borrower gets a fresh one
+    pub async fn discard(mut self) {
+        if let Some(session) = self.session.take() {
+            let _ = self.pool.manager.try_close(session).await;
+        }
+    }
+}
+
+impl<M: Manager> Deref for PooledSession<M> {
+    type Target = M::Session;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_ref()
+            .expect("session is only taken on drop")
+    }
+}
+
+impl<M: Manager> DerefMut for PooledSession<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.session
+            .as_mut()
+            .expect("session is only taken on drop")
+    }
+}
+
+impl<M: Manager> Drop for PooledSession<M> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.idle.lock().unwrap().push(session);
+        }
+    }
+}
+
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    // Sessions are numbers; `unhealthy` fails the health check for all of them
+    #[derive(Default)]
+    struct Counter {
+        connected: AtomicUsize,
+        closed: AtomicUsize,
+        unhealthy: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Manager for Counter {
+        type Session = usize;
+
+        async fn try_connect(&self) -> Result<usize> {
+            Ok(self.connected.fetch_add(1, Ordering::SeqCst))
+        }
+
+        async fn is_healthy(&self, _session: &usize) -> bool {
+            !self.unhealthy.load(Ordering::SeqCst)
+        }
+
+        async fn try_close(&self, _session: usize) -> Result<()> {
+            self.closed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_01_capabilities() {
+        let res = Capabilities::new()
+            .with_headless(true)
+            .with_user_agent("rust-demo/0.1")
+            .with_window_size(1280, 800)
+            .to_webdriver();
+
+        assert_eq!(
+            res["goog:chromeOptions"]["args"],
+            json!([
+                "--headless",
+                "--user-agent=rust-demo/0.1",
+                "--window-size=1280,800"
+            ])
+        );
+        assert_eq!(
+            res["moz:firefoxOptions"]["args"],
+            json!(["-headless", "--width=1280", "--height=800"])
+        );
+        assert_eq!(
+            res["moz:firefoxOptions"]["prefs"]["general.useragent.override"],
+            "rust-demo/0.1"
+        );
+
+        let res = Capabilities::new().to_webdriver();
+        assert_eq!(res["goog:chromeOptions"]["args"], json!([]));
+        assert!(res.get("browserName").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_02_reuse() {
+        let pool = Pool::new(Counter::default(), 2);
+
+        let first = pool.try_get().await.unwrap();
+        assert_eq!(*first, 0);
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+
+        // Same session comes back
+        let first = pool.try_get().await.unwrap();
+        let second = pool.try_get().await.unwrap();
+        assert_eq!((*first, *second), (0, 1));
+
+        // Bounded: a third borrower waits until one is returned
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), pool.try_get())
+                .await
+                .is_err()
+        );
+        drop(second);
+        let third = tokio::time::timeout(Duration::from_millis(20), pool.try_get())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*third, 1);
+        assert_eq!(pool.manager().connected.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_03_reconnect() {
+        let pool = Pool::new(Counter::default(), 1);
+
+        drop(pool.try_get().await.unwrap());
+        pool.manager().unhealthy.store(true, Ordering::SeqCst);
+
+        // The idle session fails its check, so it's closed and replaced
+        let res = pool.try_get().await.unwrap();
+        assert_eq!(*res, 1);
+        assert_eq!(pool.manager().closed.load(Ordering::SeqCst), 1);
+
+        // Discarded sessions don't go back in
+        pool.manager().unhealthy.store(false, Ordering::SeqCst);
+        res.discard().await;
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(*pool.try_get().await.unwrap(), 2);
+
+        pool.try_close_idle().await.unwrap();
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(pool.manager().closed.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_04_close() {
+        let pool = Pool::new(Counter::default(), 2);
+
+        let idle = pool.try_get().await.unwrap();
+        let lent = pool.try_get().await.unwrap();
+        drop(idle);
+
+        // Still in use at shutdown, so closed once it comes back rather than left open
+        let user = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(lent);
+        });
+        pool.try_close().await.unwrap();
+        user.await.unwrap();
+
+        assert_eq!(pool.manager().closed.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(*pool.try_get().await.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_05_capabilities_browser() {
+        let res = Capabilities::new()
+            .with_browser(Some(Browser::Firefox))
+            .with_page_load_timeout(Some(Duration::from_secs(30)))
+            .try_with_proxy("socks5://127.0.0.1:9050")
+            .unwrap()
+            .to_webdriver();
+
+        assert_eq!(res["browserName"], "firefox");
+        assert!(res.get("goog:chromeOptions").is_none());
+        assert_eq!(res["timeouts"]["pageLoad"], 30000);
+        assert_eq!(res["proxy"]["socksProxy"], "127.0.0.1:9050");
+
+        let res = Capabilities::new()
+            .with_browser(Some(Browser::Chrome))
+            .try_with_proxy("http://proxy.example.com")
+            .unwrap()
+            .to_webdriver();
+
+        assert!(res.get("moz:firefoxOptions").is_none());
+        assert_eq!(res["proxy"]["httpProxy"], "proxy.example.com:80");
+        assert_eq!(res["proxy"]["sslProxy"], "proxy.example.com:80");
+
+        assert!(Capabilities::new().try_with_proxy("proxy:3128").is_err());
+        assert!("safari".parse::<Browser>().is_err());
+    }
+}
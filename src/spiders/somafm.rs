@@ -1,185 +1,1047 @@
-use anyhow::{Context, Result};
-use fantoccini::{ClientBuilder, Locator};
-use scraper::{ElementRef, Html, Selector};
+use crate::spiders::error::{selector, Result, SpiderError};
+use crate::spiders::fetch::{Fetched, Fetcher};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use scraper::{ElementRef, Html};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::task::JoinSet;
 
 /********************************************************************************************
  * Datastructure
 ********************************************************************************************/
 
+// The song history page shows wall-clock times in San Francisco
+pub const SOMAFM_TZ: Tz = chrono_tz::US::Pacific;
+
 // Columns: Played At,	Artist,	Song,	Album
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PlaylistItem {
-    played_at: String,
+    #[serde(with = "rfc3339")]
+    played_at: DateTime<Tz>,
+    now_playing: bool,
+    artist: String,
+    song: String,
+    album: String,
+    artist_link: Option<Link>,
+    album_link: Option<Link>,
+}
+
+// Optional metadata for a `PlaylistItem`; artist, song and when it played are required
+#[derive(Clone, Debug)]
+pub struct PlaylistItemBuilder {
+    played_at: DateTime<Tz>,
+    now_playing: bool,
     artist: String,
     song: String,
     album: String,
+    artist_link: Option<Link>,
+    album_link: Option<Link>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    // somafm.com/buy/multibuy.cgi?mode=amazon&title=...&artist=...&album=...
+    AmazonSearch,
+    Bandcamp,
+    Discogs,
+    // Artist or label site
+    Homepage,
+}
+
+// Where the artist / album cell links to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    kind: LinkKind,
+    url: String,
+}
+
+impl Link {
+    // Relative hrefs ("/buy/multibuy.cgi?...") are resolved against somafm.com
+    pub fn parse(href: &str) -> Option<Link> {
+        let base = url::Url::parse("https://somafm.com/").ok()?;
+        let url = base.join(href.trim()).ok()?;
+
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let is_host = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+
+        let kind = if is_host("somafm.com") && url.path().ends_with("/multibuy.cgi") {
+            LinkKind::AmazonSearch
+        } else if is_host("bandcamp.com") {
+            LinkKind::Bandcamp
+        } else if is_host("discogs.com") {
+            LinkKind::Discogs
+        } else {
+            LinkKind::Homepage
+        };
+
+        Some(Link {
+            kind,
+            url: url.to_string(),
+        })
+    }
+
+    pub fn kind(&self) -> LinkKind {
+        self.kind
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl std::fmt::Display for PlaylistItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {} - {} ({})",
+            self.played_at.format("%Y-%m-%d %H:%M:%S"),
+            self.artist,
+            self.song,
+            self.album
+        )?;
+
+        if self.now_playing {
+            write!(f, " (Now)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PlaylistItem {
+    // Artist and song are what the YouTube search runs on, so neither may be blank
+    pub fn try_new(
+        played_at: DateTime<Tz>,
+        artist: impl Into<String>,
+        song: impl Into<String>,
+        album: impl Into<String>,
+    ) -> Result<Self> {
+        PlaylistItem::builder(played_at, artist, song)
+            .album(album)
+            .try_build()
+    }
+
+    pub fn builder(
+        played_at: DateTime<Tz>,
+        artist: impl Into<String>,
+        song: impl Into<String>,
+    ) -> PlaylistItemBuilder {
+        PlaylistItemBuilder {
+            played_at,
+            now_playing: false,
+            artist: artist.into(),
+            song: song.into(),
+            album: String::new(),
+            artist_link: None,
+            album_link: None,
+        }
+    }
+
+    pub fn played_at(&self) -> DateTime<Tz> {
+        self.played_at
+    }
+
+    pub fn is_now_playing(&self) -> bool {
+        self.now_playing
+    }
+
+    pub fn artist(&self) -> &str {
+        &self.artist
+    }
+
+    pub fn song(&self) -> &str {
+        &self.song
+    }
+
+    pub fn album(&self) -> &str {
+        &self.album
+    }
+
+    pub fn artist_link(&self) -> Option<&Link> {
+        self.artist_link.as_ref()
+    }
+
+    pub fn album_link(&self) -> Option<&Link> {
+        self.album_link.as_ref()
+    }
+}
+
+impl PlaylistItemBuilder {
+    pub fn album(mut self, album: impl Into<String>) -> Self {
+        self.album = album.into();
+        self
+    }
+
+    pub fn now_playing(mut self, now_playing: bool) -> Self {
+        self.now_playing = now_playing;
+        self
+    }
+
+    pub fn artist_link(mut self, link: Link) -> Self {
+        self.artist_link = Some(link);
+        self
+    }
+
+    pub fn album_link(mut self, link: Link) -> Self {
+        self.album_link = Some(link);
+        self
+    }
+
+    pub fn try_build(self) -> Result<PlaylistItem> {
+        if self.artist.trim().is_empty() {
+            return Err(SpiderError::parse("playlist item", "artist is empty"));
+        }
+        if self.song.trim().is_empty() {
+            return Err(SpiderError::parse("playlist item", "song is empty"));
+        }
+
+        Ok(PlaylistItem {
+            played_at: self.played_at,
+            now_playing: self.now_playing,
+            artist: self.artist,
+            song: self.song,
+            album: self.album,
+            artist_link: self.artist_link,
+            album_link: self.album_link,
+        })
+    }
+}
+
+// One row of the song history table
+#[derive(Clone, Debug, PartialEq)]
+pub enum HistoryEntry {
+    Track(PlaylistItem),
+    // "Break / Station ID". `duration` is None for the newest row, which hasn't ended yet.
+    Break {
+        played_at: DateTime<Tz>,
+        duration: Option<Duration>,
+        label: String,
+    },
+}
+
+impl HistoryEntry {
+    pub fn played_at(&self) -> DateTime<Tz> {
+        match self {
+            HistoryEntry::Track(item) => item.played_at,
+            HistoryEntry::Break { played_at, .. } => *played_at,
+        }
+    }
+
+    pub fn as_track(&self) -> Option<&PlaylistItem> {
+        match self {
+            HistoryEntry::Track(item) => Some(item),
+            HistoryEntry::Break { .. } => None,
+        }
+    }
+
+    pub fn into_track(self) -> Option<PlaylistItem> {
+        match self {
+            HistoryEntry::Track(item) => Some(item),
+            HistoryEntry::Break { .. } => None,
+        }
+    }
+
+    pub fn is_break(&self) -> bool {
+        matches!(self, HistoryEntry::Break { .. })
+    }
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryEntry::Track(item) => write!(f, "{}", item),
+            HistoryEntry::Break {
+                played_at,
+                duration,
+                label,
+            } => {
+                write!(f, "{}  [{}]", played_at.format("%Y-%m-%d %H:%M:%S"), label)?;
+
+                if let Some(duration) = duration {
+                    write!(f, " {}s", duration.num_seconds())?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+// SomaFM channel slug, as in https://somafm.com/<slug>/songhistory.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Station(Cow<'static, str>);
+
+impl Station {
+    pub const GROOVE_SALAD: Station = Station(Cow::Borrowed("groovesalad"));
+    pub const DRONE_ZONE: Station = Station(Cow::Borrowed("dronezone"));
+    pub const SECRET_AGENT: Station = Station(Cow::Borrowed("secretagent"));
+    pub const LUSH: Station = Station(Cow::Borrowed("lush"));
+    pub const DEEP_SPACE_ONE: Station = Station(Cow::Borrowed("deepspaceone"));
+    pub const SPACE_STATION: Station = Station(Cow::Borrowed("spacestation"));
+    pub const BEAT_BLENDER: Station = Station(Cow::Borrowed("beatblender"));
+    pub const DEF_CON: Station = Station(Cow::Borrowed("defcon"));
+    pub const FLUID: Station = Station(Cow::Borrowed("fluid"));
+    pub const INDIE_POP: Station = Station(Cow::Borrowed("indiepop"));
+    pub const SONIC_UNIVERSE: Station = Station(Cow::Borrowed("sonicuniverse"));
+    pub const LEFT_COAST_70S: Station = Station(Cow::Borrowed("seventies"));
+
+    pub const KNOWN: [Station; 12] = [
+        Station::GROOVE_SALAD,
+        Station::DRONE_ZONE,
+        Station::SECRET_AGENT,
+        Station::LUSH,
+        Station::DEEP_SPACE_ONE,
+        Station::SPACE_STATION,
+        Station::BEAT_BLENDER,
+        Station::DEF_CON,
+        Station::FLUID,
+        Station::INDIE_POP,
+        Station::SONIC_UNIVERSE,
+        Station::LEFT_COAST_70S,
+    ];
+
+    pub fn slug(&self) -> &str {
+        &self.0
+    }
+
+    pub fn song_history_url(&self) -> String {
+        format!("https://somafm.com/{}/songhistory.html", self.0)
+    }
+}
+
+impl Default for Station {
+    fn default() -> Self {
+        Station::GROOVE_SALAD
+    }
+}
+
+impl std::fmt::Display for Station {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Slugs are short, lowercase and alphanumeric ("groovesalad", "u80s", "bagel")
+impl FromStr for Station {
+    type Err = SpiderError;
+
+    fn from_str(slug: &str) -> Result<Self, Self::Err> {
+        let valid = !slug.is_empty()
+            && slug.len() <= 32
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+
+        if !valid {
+            return Err(SpiderError::parse(
+                format!("station \"{}\"", slug),
+                "expected a lowercase SomaFM channel slug such as \"groovesalad\"",
+            ));
+        }
+
+        Ok(Station(Cow::Owned(slug.to_string())))
+    }
+}
+
+impl TryFrom<String> for Station {
+    type Error = SpiderError;
+
+    fn try_from(slug: String) -> Result<Self, Self::Error> {
+        slug.parse()
+    }
+}
+
+impl From<Station> for String {
+    fn from(station: Station) -> Self {
+        station.0.into_owned()
+    }
+}
+
+// Strict fails the whole scrape on the first bad row, Lenient skips it and keeps going
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScrapeMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+// A row that was skipped in lenient mode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RowDiagnostic {
+    index: usize,
+    reason: String,
+    html: String,
+}
+
+impl RowDiagnostic {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+}
+
+impl std::fmt::Display for RowDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {}: {}", self.index, self.reason)
+    }
+}
+
+impl From<RowDiagnostic> for SpiderError {
+    fn from(diagnostic: RowDiagnostic) -> Self {
+        SpiderError::MalformedRow {
+            index: diagnostic.index,
+            reason: diagnostic.reason,
+            html: diagnostic.html,
+        }
+    }
+}
+
+// Everything that parsed, plus what didn't
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScrapeReport<T> {
+    items: Vec<T>,
+    diagnostics: Vec<RowDiagnostic>,
+}
+
+impl<T> Default for ScrapeReport<T> {
+    fn default() -> Self {
+        ScrapeReport {
+            items: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+impl<T> ScrapeReport<T> {
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn diagnostics(&self) -> &[RowDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+}
+
+// A scraped song history, tagged with the channel it came from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StationPlaylist {
+    station: Station,
+    playlist: Vec<PlaylistItem>,
+    #[serde(skip)]
+    diagnostics: Vec<RowDiagnostic>,
+}
+
+impl StationPlaylist {
+    pub fn station(&self) -> &Station {
+        &self.station
+    }
+
+    pub fn playlist(&self) -> &[PlaylistItem] {
+        &self.playlist
+    }
+
+    pub fn diagnostics(&self) -> &[RowDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_playlist(self) -> Vec<PlaylistItem> {
+        self.playlist
+    }
+}
+
+impl std::fmt::Display for StationPlaylist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "# {}", self.station)?;
+
+        for item in &self.playlist {
+            write!(f, "\n{}", item)?;
+        }
+
+        Ok(())
+    }
+}
+
+// One entry of https://somafm.com/channels.json
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Channel {
+    station: Station,
+    title: String,
+    genres: Vec<String>,
+    dj: String,
+    description: String,
+    listeners: u32,
+    streams: Vec<Stream>,
+}
+
+impl Channel {
+    pub fn station(&self) -> &Station {
+        &self.station
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn genres(&self) -> &[String] {
+        &self.genres
+    }
+
+    pub fn dj(&self) -> &str {
+        &self.dj
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn listeners(&self) -> u32 {
+        self.listeners
+    }
+
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<16} {:<28} {:>6}  {}",
+            self.station,
+            self.title,
+            self.listeners,
+            self.genres.join("|")
+        )
+    }
+}
+
+// A .pls playlist pointing at the actual audio stream
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stream {
+    url: String,
+    format: String,
+    quality: String,
+}
+
+impl Stream {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn quality(&self) -> &str {
+        &self.quality
+    }
+}
+
+// channels.json as served; numbers come back as strings
+#[derive(Deserialize)]
+struct ChannelsJson {
+    channels: Vec<ChannelJson>,
+}
+
+#[derive(Deserialize)]
+struct ChannelJson {
+    id: String,
+    title: String,
+    #[serde(default)]
+    genre: String,
+    #[serde(default)]
+    dj: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    listeners: String,
+    #[serde(default)]
+    playlists: Vec<Stream>,
+}
+
+impl TryFrom<ChannelJson> for Channel {
+    type Error = SpiderError;
+
+    fn try_from(raw: ChannelJson) -> Result<Self, Self::Error> {
+        let station = raw.id.parse::<Station>()?;
+
+        let listeners = match raw.listeners.trim() {
+            "" => 0,
+            count => count
+                .parse::<u32>()
+                .map_err(|e| SpiderError::parse(format!("listener count for {}", station), e))?,
+        };
+
+        let genres = raw
+            .genre
+            .split('|')
+            .map(str::trim)
+            .filter(|genre| !genre.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Ok(Channel {
+            station,
+            title: html_escape::decode_html_entities(&raw.title).to_string(),
+            genres,
+            dj: html_escape::decode_html_entities(&raw.dj).to_string(),
+            description: html_escape::decode_html_entities(&raw.description).to_string(),
+            listeners,
+            streams: raw.playlists,
+        })
+    }
 }
 
 /********************************************************************************************
  * Methods
 ********************************************************************************************/
-pub async fn try_get_playlist() -> Result<String> {
-    // Connecting using "native" TLS (with feature `native-tls`; on by default)
-    //let c = ClientBuilder::native().connect("http://localhost:4444").await.expect("failed to connect to WebDriver");
-    // Connecting using Rustls (with feature `rustls-tls`)
-    let c = ClientBuilder::rustls()
-        .connect("http://localhost:8888")
-        .await
-        .expect("failed to connect to WebDriver");
+pub async fn try_get_playlist(fetcher: &dyn Fetcher, station: &Station) -> Result<String> {
+    let res = try_get_playlist_dated(fetcher, station).await?;
+
+    Ok(res.body)
+}
 
-    c.goto("https://somafm.com/groovesalad/songhistory.html")
+// With when the page was fetched, to scrape it against
+pub async fn try_get_playlist_dated(fetcher: &dyn Fetcher, station: &Station) -> Result<Fetched> {
+    let res = fetcher
+        .try_fetch_element_dated(station.song_history_url().as_str(), "#playinc")
         .await?;
 
-    let html = c.find(Locator::Css("#playinc")).await?.html(true).await?;
+    Ok(Fetched {
+        body: html_escape::decode_html_entities(res.body.as_str()).to_string(),
+        fetched_at: res.fetched_at,
+    })
+}
 
-    c.close().await?;
+// Timestamps are written with their UTC offset and read back into the field's time zone: the
+// station's for a `DateTime<Tz>`, UTC for a `DateTime<Utc>`
+pub(crate) mod rfc3339 {
+    use super::SOMAFM_TZ;
+    use chrono::{DateTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+
+    pub trait Zone: TimeZone {
+        fn zone() -> Self;
+    }
 
-    let res = html_escape::decode_html_entities(html.as_str()).to_string();
+    impl Zone for Tz {
+        fn zone() -> Self {
+            SOMAFM_TZ
+        }
+    }
 
-    Ok(res)
+    impl Zone for Utc {
+        fn zone() -> Self {
+            Utc
+        }
+    }
+
+    pub fn serialize<Z, S>(at: &DateTime<Z>, s: S) -> Result<S::Ok, S::Error>
+    where
+        Z: TimeZone,
+        Z::Offset: Display,
+        S: Serializer,
+    {
+        s.serialize_str(at.to_rfc3339().as_str())
+    }
+
+    pub fn deserialize<'de, Z: Zone, D: Deserializer<'de>>(d: D) -> Result<DateTime<Z>, D::Error> {
+        let text = String::deserialize(d)?;
+
+        let res = DateTime::parse_from_rfc3339(text.as_str())
+            .map_err(serde::de::Error::custom)?
+            .with_timezone(&Z::zone());
+
+        Ok(res)
+    }
 }
 
-pub fn try_scrape_html(html_escaped_payload: &str) -> Result<Vec<PlaylistItem>> {
-    let mut res = Vec::<PlaylistItem>::new();
+/*
+    The "Played At" column only has a time of day, newest row first:
+
+        00:02:10 (Now)    <- reference date
+        23:58:00          <- later than the row above, so it was the day before
+        23:50:00
+
+    Walk down the table from the reference time and step back a day whenever the clock goes up.
+    The newest row goes on whichever day puts it at most PLAYED_AT_SKEW after the reference: with
+    a little clock skew, or a track change right after the fetch, it can be a bit later than the
+    reference without being from yesterday.
+*/
+const PLAYED_AT_SKEW: Duration = Duration::hours(1);
+
+struct PlayedAtClock {
+    tz: Tz,
+    reference: NaiveDateTime,
+    date: NaiveDate,
+    // None until the first row
+    last: Option<NaiveTime>,
+}
+
+impl PlayedAtClock {
+    fn new(reference: DateTime<Tz>) -> Self {
+        PlayedAtClock {
+            tz: reference.timezone(),
+            reference: reference.naive_local(),
+            date: reference.date_naive(),
+            last: None,
+        }
+    }
+
+    // "14:21:19\u{a0} (Now) " -> (14:21:19 on the right day, true)
+    fn try_next(&mut self, cell: &str) -> Result<(DateTime<Tz>, bool), String> {
+        let text = cell.trim();
+        let now_playing = text.contains("(Now)");
+
+        let token = text
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let time = NaiveTime::parse_from_str(token, "%H:%M:%S")
+            .map_err(|e| format!("invalid played at time {:?}: {}", cell, e))?;
+
+        match self.last {
+            Some(last) if time > last => self.date -= Duration::days(1),
+            Some(_) => (),
+            None => {
+                let latest = self.reference + PLAYED_AT_SKEW;
+                let at = self.date.and_time(time);
+
+                if at > latest {
+                    self.date -= Duration::days(1);
+                } else if at + Duration::days(1) <= latest {
+                    self.date += Duration::days(1);
+                }
+            }
+        }
+        self.last = Some(time);
+
+        let res = self
+            .tz
+            .from_local_datetime(&self.date.and_time(time))
+            .earliest()
+            .ok_or_else(|| format!("{} {} does not exist in {}", self.date, time, self.tz))?;
+
+        Ok((res, now_playing))
+    }
+}
+
+pub fn try_scrape_history(html_escaped_payload: &str) -> Result<Vec<HistoryEntry>> {
+    try_scrape_history_at(html_escaped_payload, Utc::now().with_timezone(&SOMAFM_TZ))
+}
+
+// `reference` is when the page was fetched, in the station's time zone
+pub fn try_scrape_history_at(
+    html_escaped_payload: &str,
+    reference: DateTime<Tz>,
+) -> Result<Vec<HistoryEntry>> {
+    let res = try_scrape_history_report(html_escaped_payload, reference, ScrapeMode::Strict)?;
+
+    Ok(res.items)
+}
+
+// In `ScrapeMode::Lenient`, rows that can't be read are skipped and reported in `diagnostics`
+pub fn try_scrape_history_report(
+    html_escaped_payload: &str,
+    reference: DateTime<Tz>,
+    mode: ScrapeMode,
+) -> Result<ScrapeReport<HistoryEntry>> {
+    let mut res = ScrapeReport::<HistoryEntry>::default();
+    let mut clock = PlayedAtClock::new(reference);
+    let mut newer: Option<DateTime<Tz>> = None;
 
     let fragment = Html::parse_fragment(html_escaped_payload);
 
-    let table_selector =
-        Selector::parse("table").map_err(|e| anyhow::anyhow!("An error occurred: {:#?}", e))?;
-    let tbody_selector =
-        Selector::parse("tbody").map_err(|e| anyhow::anyhow!("An error occurred: {:#?}", e))?;
-    let tr_selector =
-        Selector::parse("tr").map_err(|e| anyhow::anyhow!("An error occurred: {:#?}", e))?;
-    let td_selector =
-        Selector::parse("td").map_err(|e| anyhow::anyhow!("An error occurred: {:#?}", e))?;
-    let a_selector =
-        Selector::parse("a").map_err(|e| anyhow::anyhow!("An error occurred: {:#?}", e))?;
+    let table_selector = selector("table")?;
+    let tbody_selector = selector("tbody")?;
+    let tr_selector = selector("tr")?;
+    let td_selector = selector("td")?;
+    let a_selector = selector("a")?;
 
     let table = fragment
         .select(&table_selector)
         .next()
-        .context("An error occurred")?
+        .ok_or_else(|| SpiderError::missing_element("song history", "table"))?
         .select(&tbody_selector)
         .next()
-        .context("An error occurred")?;
+        .ok_or_else(|| SpiderError::missing_element("song history", "tbody"))?;
 
     'next_row: for (index, row) in table.select(&tr_selector).enumerate() {
         let tds = row.select(&td_selector).collect::<Vec<_>>();
         /*
           Ignore first (heading) and second (separator) row
-          Ignore any row that is tds.len() < 5
-          Ignore last (separator) row -- above rule captures this rule
+          Ignore last (separator) row -- tds.len() < 2 captures this rule
+          Rows with tds.len() < 5 are "Break / Station ID"
         */
 
-        if index < 2 || tds.len() < 5 {
+        if index < 2 || tds.len() < 2 {
             continue 'next_row;
         }
 
-        let get_inner = |td: ElementRef| -> Result<String> {
-            let res = td
-                .select(&a_selector)
-                .next()
-                .context("An error occurred")?
-                .inner_html();
-
-            Ok(res)
+        let malformed = |reason: String| RowDiagnostic {
+            index,
+            reason,
+            html: row.html(),
         };
 
-        let time = html_escape::decode_html_entities(tds[0].inner_html().as_str()).to_string()[..8]
-            .to_string();
+        let parsed = clock
+            .try_next(html_escape::decode_html_entities(tds[0].inner_html().as_str()).as_ref())
+            .map_err(malformed)
+            .and_then(|(played_at, now_playing)| {
+                if tds.len() < 5 {
+                    // Rows are newest first, so a break lasts until the row above it started
+                    return Ok(HistoryEntry::Break {
+                        played_at,
+                        duration: newer.map(|until| until - played_at),
+                        label: html_escape::decode_html_entities(
+                            tds[1].text().collect::<String>().trim(),
+                        )
+                        .to_string(),
+                    });
+                }
 
-        let item = PlaylistItem {
-            played_at: time,
-            artist: html_escape::decode_html_entities(get_inner(tds[1])?.as_str()).to_string(),
-            song: html_escape::decode_html_entities(tds[2].inner_html().as_str()).to_string(),
-            album: html_escape::decode_html_entities(get_inner(tds[3])?.as_str()).to_string(),
-        };
+                let get_inner = |td: ElementRef, column: &str| -> Result<String, RowDiagnostic> {
+                    let res = td
+                        .select(&a_selector)
+                        .next()
+                        .ok_or_else(|| malformed(format!("no link in the {} column", column)))?
+                        .inner_html();
+
+                    Ok(res)
+                };
+
+                let get_link = |td: ElementRef| -> Option<Link> {
+                    td.select(&a_selector)
+                        .next()
+                        .and_then(|a| a.value().attr("href"))
+                        .and_then(Link::parse)
+                };
+
+                let item = PlaylistItem {
+                    played_at,
+                    now_playing,
+                    artist: html_escape::decode_html_entities(
+                        get_inner(tds[1], "artist")?.as_str(),
+                    )
+                    .to_string(),
+                    song: html_escape::decode_html_entities(tds[2].inner_html().as_str())
+                        .to_string(),
+                    album: html_escape::decode_html_entities(get_inner(tds[3], "album")?.as_str())
+                        .to_string(),
+                    artist_link: get_link(tds[1]),
+                    album_link: get_link(tds[3]),
+                };
+
+                Ok(HistoryEntry::Track(item))
+            });
 
-        res.push(item);
+        match (parsed, mode) {
+            (Ok(entry), _) => {
+                newer = Some(entry.played_at());
+                res.items.push(entry);
+            }
+            (Err(diagnostic), ScrapeMode::Lenient) => res.diagnostics.push(diagnostic),
+            (Err(diagnostic), ScrapeMode::Strict) => return Err(diagnostic.into()),
+        }
     }
 
     Ok(res)
 }
 
+// Just the songs, without the breaks
+pub fn try_scrape_html(html_escaped_payload: &str) -> Result<Vec<PlaylistItem>> {
+    try_scrape_html_at(html_escaped_payload, Utc::now().with_timezone(&SOMAFM_TZ))
+}
 
+pub fn try_scrape_html_at(
+    html_escaped_payload: &str,
+    reference: DateTime<Tz>,
+) -> Result<Vec<PlaylistItem>> {
+    let res = try_scrape_html_report(html_escaped_payload, reference, ScrapeMode::Strict)?;
 
-/********************************************************************************************
- * TESTS
-********************************************************************************************/
+    Ok(res.items)
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub fn try_scrape_html_report(
+    html_escaped_payload: &str,
+    reference: DateTime<Tz>,
+    mode: ScrapeMode,
+) -> Result<ScrapeReport<PlaylistItem>> {
+    let res = try_scrape_history_report(html_escaped_payload, reference, mode)?;
+
+    Ok(ScrapeReport {
+        items: res
+            .items
+            .into_iter()
+            .filter_map(HistoryEntry::into_track)
+            .collect(),
+        diagnostics: res.diagnostics,
+    })
+}
 
-    #[test]
-    fn test_01() {
-        println!("Hello somafm test!");
+// Fetch several stations at once (one WebDriver session each, when fetching through a browser).
+// Results keep the order of `stations`.
+pub async fn try_get_playlists(
+    fetcher: Arc<dyn Fetcher>,
+    stations: &[Station],
+) -> Result<Vec<(Station, Fetched)>> {
+    let mut tasks = JoinSet::new();
+
+    for (index, station) in stations.iter().cloned().enumerate() {
+        let fetcher = fetcher.clone();
+
+        tasks.spawn(async move {
+            let html = try_get_playlist_dated(fetcher.as_ref(), &station).await?;
 
-        assert!(true)
+            Ok::<_, SpiderError>((index, station, html))
+        });
     }
 
-    #[tokio::test]
-    #[ignore]
-    async fn test_02_connect_to_somafm() {
-        let res = async {
-            use fantoccini::{ClientBuilder, Locator};
+    let mut res = Vec::<(usize, Station, Fetched)>::with_capacity(stations.len());
 
-            // Connecting using "native" TLS (with feature `native-tls`; on by default)
-            //let c = ClientBuilder::native().connect("http://localhost:4444").await.expect("failed to connect to WebDriver");
-            // Connecting using Rustls (with feature `rustls-tls`)
-            let c = ClientBuilder::rustls()
-                .connect("http://localhost:8888")
-                .await
-                .expect("failed to connect to WebDriver");
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(fetched) => res.push(fetched?),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
 
-            c.goto("https://somafm.com/groovesalad/songhistory.html")
-                .await?;
+    res.sort_by_key(|(index, _, _)| *index);
 
-            let html = c.find(Locator::Css("#playinc")).await?.html(true).await?;
+    Ok(res
+        .into_iter()
+        .map(|(_, station, html)| (station, html))
+        .collect())
+}
 
-            println!("HTML: {}", html);
+pub fn try_scrape_station(
+    station: Station,
+    html_escaped_payload: &str,
+    mode: ScrapeMode,
+) -> Result<StationPlaylist> {
+    try_scrape_station_at(
+        station,
+        html_escaped_payload,
+        Utc::now().with_timezone(&SOMAFM_TZ),
+        mode,
+    )
+}
 
-            c.close().await
-        };
+// `reference` is when the page was fetched, see `try_scrape_history_at`
+pub fn try_scrape_station_at(
+    station: Station,
+    html_escaped_payload: &str,
+    reference: DateTime<Tz>,
+    mode: ScrapeMode,
+) -> Result<StationPlaylist> {
+    let report = try_scrape_html_report(html_escaped_payload, reference, mode)?;
+
+    Ok(StationPlaylist {
+        station,
+        playlist: report.items,
+        diagnostics: report.diagnostics,
+    })
+}
 
-        match res.await {
-            Ok(_) => {}
-            Err(e) => {
-                println!("ERROR: {:#?}", e);
-            }
-        };
+pub async fn try_fetch_playlists(
+    fetcher: Arc<dyn Fetcher>,
+    stations: &[Station],
+    mode: ScrapeMode,
+) -> Result<Vec<StationPlaylist>> {
+    try_get_playlists(fetcher, stations)
+        .await?
+        .into_iter()
+        .map(|(station, html)| {
+            let reference = html.fetched_at.with_timezone(&SOMAFM_TZ);
+
+            try_scrape_station_at(station, html.body.as_str(), reference, mode)
+        })
+        .collect()
+}
+
+pub async fn try_get_channels(fetcher: &dyn Fetcher) -> Result<String> {
+    fetcher
+        .try_fetch_text("https://somafm.com/channels.json")
+        .await
+}
+
+pub fn try_parse_channels(json_payload: &str) -> Result<Vec<Channel>> {
+    let raw = serde_json::from_str::<ChannelsJson>(json_payload)
+        .map_err(|e| SpiderError::parse("channels.json", e))?;
+
+    raw.channels.into_iter().map(Channel::try_from).collect()
+}
+
+pub async fn try_fetch_channels(fetcher: &dyn Fetcher) -> Result<Vec<Channel>> {
+    let json = try_get_channels(fetcher).await?;
+
+    try_parse_channels(json.as_str())
+}
+
+// For the tests of the modules built on `PlaylistItem`: a time on one fixed day, and a song with
+// no album played then
+#[cfg(test)]
+pub(crate) fn test_played_at(h: u32, m: u32, s: u32) -> DateTime<Tz> {
+    SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, h, m, s).unwrap()
+}
+
+#[cfg(test)]
+pub(crate) fn test_song(played_at: DateTime<Tz>, artist: &str, song: &str) -> PlaylistItem {
+    PlaylistItem::try_new(played_at, artist, song, "").unwrap()
+}
 
-        assert!(true)
-    }
 
-    #[tokio::test]
-    async fn test_03_escape_html() {
-        let html_payload = r#"
-<table width="100%" border="0">
-<tbody>
-<tr>
- <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td>
- </tr>
 
- <tr>
- <td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td>
- </tr>
+/********************************************************************************************
+ * TESTS
+********************************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiders::config::DriverConfig;
+    use crate::spiders::fetch::{HttpFetcher, StubFetcher, WebDriverFetcher};
+    use crate::spiders::session::WebDriverManager;
+
+    const SONG_HISTORY_HTML: &str = r#"
+<table width="100%" border="0">
+<tbody><tr>
+  <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td></tr><tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
 
 <!-- line 1 -->
-<tr><td>14:21:19&nbsp; (Now) </td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Last%20Place%20To%20Hide&amp;artist=Welder%20%26%20Seed&amp;album=Chime&amp;album=Chime" title="Search Amazon for Welder &amp; Seed">Welder &amp; Seed</a></td><td>Last Place To Hide</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Last%20Place%20To%20Hide&amp;album=Chime&amp;artist=Welder%20%26%20Seed" title="Search Amazon for Chime">Chime</a></td>
+<tr><td>14:21:19  (Now) </td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Last%20Place%20To%20Hide&artist=Welder%20%26%20Seed&album=Chime&album=Chime" title="Search Amazon for Welder & Seed">Welder & Seed</a></td><td>Last Place To Hide</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Last%20Place%20To%20Hide&album=Chime&artist=Welder%20%26%20Seed" title="Search Amazon for Chime">Chime</a></td>
 <td></td>
 </tr>
 
 <!-- line 2 -->
 <tr><td>14:17:01</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Our%20Way&amp;artist=Hazy%20J&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019" title="Search Amazon for Hazy J">Hazy J</a></td><td>Our Way</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Our%20Way&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019&amp;artist=Hazy%20J" title="Search Amazon for Cafe del Mar, Vol. 19">Cafe del Mar, Vol. 19</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J&album=Cafe%20del%20Mar%2C%20Vol.%2019&album=Cafe%20del%20Mar%2C%20Vol.%2019" title="Search Amazon for Hazy J">Hazy J</a></td><td>Our Way</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&album=Cafe%20del%20Mar%2C%20Vol.%2019&artist=Hazy%20J" title="Search Amazon for Cafe del Mar, Vol. 19">Cafe del Mar, Vol. 19</a></td>
 <td></td>
 </tr>
 
 <!-- line 3 -->
 <tr><td>14:12:06</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Cowboy%20Hero&amp;artist=Experiment&amp;album=What%20Goes%20Up&amp;album=What%20Goes%20Up" title="Search Amazon for Experiment">Experiment</a></td><td>Cowboy Hero</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Cowboy%20Hero&amp;album=What%20Goes%20Up&amp;artist=Experiment" title="Search Amazon for What Goes Up">What Goes Up</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Cowboy%20Hero&artist=Experiment&album=What%20Goes%20Up&album=What%20Goes%20Up" title="Search Amazon for Experiment">Experiment</a></td><td>Cowboy Hero</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Cowboy%20Hero&album=What%20Goes%20Up&artist=Experiment" title="Search Amazon for What Goes Up">What Goes Up</a></td>
 <td></td>
 </tr>
 
@@ -189,13 +1051,13 @@ mod tests {
 
 <!-- line 5 -->
 <tr><td>14:07:32</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Glamourgirl&amp;artist=Alex%20Cortiz&amp;album=Magnifico%21&amp;album=Magnifico%21" title="Search Amazon for Alex Cortiz">Alex Cortiz</a></td><td>Glamourgirl</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Glamourgirl&amp;album=Magnifico%21&amp;artist=Alex%20Cortiz" title="Search Amazon for Magnifico!">Magnifico!</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz&album=Magnifico%21&album=Magnifico%21" title="Search Amazon for Alex Cortiz">Alex Cortiz</a></td><td>Glamourgirl</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&album=Magnifico%21&artist=Alex%20Cortiz" title="Search Amazon for Magnifico!">Magnifico!</a></td>
 <td></td>
 </tr>
 
 <!-- line 6 -->
 <tr><td>14:02:23</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&amp;artist=Kiln&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D" title="Search Amazon for Kiln">Kiln</a></td><td>Ore Corymb [Bursting Rainbow C</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&amp;artist=Kiln" title="Search Amazon for Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]">Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&artist=Kiln&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D" title="Search Amazon for Kiln">Kiln</a></td><td>Ore Corymb [Bursting Rainbow C</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&artist=Kiln" title="Search Amazon for Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]">Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]</a></td>
 <td></td>
 </tr>
 
@@ -207,13 +1069,13 @@ mod tests {
 
 <!-- line 8 -->
 <tr><td>13:53:16</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Porta%20Mia%20Via&amp;artist=Campa&amp;album=Klassik%20Lounge%20-%20Werk%204&amp;album=Klassik%20Lounge%20-%20Werk%204" title="Search Amazon for Campa">Campa</a></td><td>Porta Mia Via</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Porta%20Mia%20Via&amp;album=Klassik%20Lounge%20-%20Werk%204&amp;artist=Campa" title="Search Amazon for Klassik Lounge - Werk 4">Klassik Lounge - Werk 4</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Porta%20Mia%20Via&artist=Campa&album=Klassik%20Lounge%20-%20Werk%204&album=Klassik%20Lounge%20-%20Werk%204" title="Search Amazon for Campa">Campa</a></td><td>Porta Mia Via</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Porta%20Mia%20Via&album=Klassik%20Lounge%20-%20Werk%204&artist=Campa" title="Search Amazon for Klassik Lounge - Werk 4">Klassik Lounge - Werk 4</a></td>
 <td></td>
 </tr>
 
 <!-- line 9 -->
 <tr><td>13:48:52</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Astro%20Radio&amp;artist=Or-If-Is&amp;album=This%20Is&amp;album=This%20Is" title="Search Amazon for Or-If-Is">Or-If-Is</a></td><td>Astro Radio</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Astro%20Radio&amp;album=This%20Is&amp;artist=Or-If-Is" title="Search Amazon for This Is">This Is</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Astro%20Radio&artist=Or-If-Is&album=This%20Is&album=This%20Is" title="Search Amazon for Or-If-Is">Or-If-Is</a></td><td>Astro Radio</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Astro%20Radio&album=This%20Is&artist=Or-If-Is" title="Search Amazon for This Is">This Is</a></td>
 <td></td>
 </tr>
 
@@ -225,31 +1087,31 @@ mod tests {
 
 <!-- line 11 -->
 <tr><td>13:38:14</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Even%20So&amp;artist=Setsuna&amp;album=Autumn%20Time%20Vol.%203&amp;album=Autumn%20Time%20Vol.%203" title="Search Amazon for Setsuna">Setsuna</a></td><td>Even So</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Even%20So&amp;album=Autumn%20Time%20Vol.%203&amp;artist=Setsuna" title="Search Amazon for Autumn Time Vol. 3">Autumn Time Vol. 3</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Even%20So&artist=Setsuna&album=Autumn%20Time%20Vol.%203&album=Autumn%20Time%20Vol.%203" title="Search Amazon for Setsuna">Setsuna</a></td><td>Even So</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Even%20So&album=Autumn%20Time%20Vol.%203&artist=Setsuna" title="Search Amazon for Autumn Time Vol. 3">Autumn Time Vol. 3</a></td>
 <td></td>
 </tr>
 
 <!-- line 12 -->
 <tr><td>13:32:54</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&amp;artist=Yoko%20Kanno&amp;album=Cowboy%20Bebop%20Remixes&amp;album=Cowboy%20Bebop%20Remixes" title="Search Amazon for Yoko Kanno">Yoko Kanno</a></td><td>Forever Broke (Fila Brazillia Remix)</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&amp;album=Cowboy%20Bebop%20Remixes&amp;artist=Yoko%20Kanno" title="Search Amazon for Cowboy Bebop Remixes">Cowboy Bebop Remixes</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&artist=Yoko%20Kanno&album=Cowboy%20Bebop%20Remixes&album=Cowboy%20Bebop%20Remixes" title="Search Amazon for Yoko Kanno">Yoko Kanno</a></td><td>Forever Broke (Fila Brazillia Remix)</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&album=Cowboy%20Bebop%20Remixes&artist=Yoko%20Kanno" title="Search Amazon for Cowboy Bebop Remixes">Cowboy Bebop Remixes</a></td>
 <td></td>
 </tr>
 
 <!-- line 13 -->
 <tr><td>13:27:46</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&amp;artist=Lounge%20Deluxe&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29" title="Search Amazon for Lounge Deluxe">Lounge Deluxe</a></td><td>This Is All I Ask (Oxygene Dow</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&amp;artist=Lounge%20Deluxe" title="Search Amazon for Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)">Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&artist=Lounge%20Deluxe&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29" title="Search Amazon for Lounge Deluxe">Lounge Deluxe</a></td><td>This Is All I Ask (Oxygene Dow</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&artist=Lounge%20Deluxe" title="Search Amazon for Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)">Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)</a></td>
 <td></td>
 </tr>
 
 <!-- line 14 -->
 <tr><td>13:21:03</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Knuddelmaus&amp;artist=Ulrich%20Schnauss&amp;album=Far%20Away%20Trains%20Passing%20By&amp;album=Far%20Away%20Trains%20Passing%20By" title="Search Amazon for Ulrich Schnauss">Ulrich Schnauss</a></td><td>Knuddelmaus</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Knuddelmaus&amp;album=Far%20Away%20Trains%20Passing%20By&amp;artist=Ulrich%20Schnauss" title="Search Amazon for Far Away Trains Passing By">Far Away Trains Passing By</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Knuddelmaus&artist=Ulrich%20Schnauss&album=Far%20Away%20Trains%20Passing%20By&album=Far%20Away%20Trains%20Passing%20By" title="Search Amazon for Ulrich Schnauss">Ulrich Schnauss</a></td><td>Knuddelmaus</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Knuddelmaus&album=Far%20Away%20Trains%20Passing%20By&artist=Ulrich%20Schnauss" title="Search Amazon for Far Away Trains Passing By">Far Away Trains Passing By</a></td>
 <td></td>
 </tr>
 
 <!-- line 15 -->
 <tr><td>13:15:01</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Sola%20Systim&amp;artist=Underworld&amp;album=Ansum&amp;album=Ansum" title="Search Amazon for Underworld">Underworld</a></td><td>Sola Systim</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Sola%20Systim&amp;album=Ansum&amp;artist=Underworld" title="Search Amazon for Ansum">Ansum</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Sola%20Systim&artist=Underworld&album=Ansum&album=Ansum" title="Search Amazon for Underworld">Underworld</a></td><td>Sola Systim</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Sola%20Systim&album=Ansum&artist=Underworld" title="Search Amazon for Ansum">Ansum</a></td>
 <td></td>
 </tr>
 
@@ -275,44 +1137,77 @@ mod tests {
 
 <!-- line 20 -->
 <tr><td>13:01:23</td>
-<td><a target="_blank" href="https://www.discogs.com/artist/Sofa+Lofa" title="Go to Sofa Lofa’s site">Sofa Lofa</a></td><td>Magic Shopkeeper</td><td><a target="_blank" href="https://bathysphere.co.uk" title="More information for Bathesphere recordings 7&quot;">Bathesphere recordings 7"</a></td>
+<td><a target="_blank" href="https://www.discogs.com/artist/Sofa+Lofa" title="Go to Sofa Lofa’s site">Sofa Lofa</a></td><td>Magic Shopkeeper</td><td><a target="_blank" href="https://bathysphere.co.uk" title="More information for Bathesphere recordings 7"">Bathesphere recordings 7"</a></td>
 <td></td>
 </tr>
-
 <tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
-
 </tbody></table>
-      "#.to_string();
+      "#;
 
-        let res = html_escape::decode_html_entities(html_payload.as_str()).to_string();
+    #[test]
+    fn test_01() {
+        println!("Hello somafm test!");
+    }
 
-        println!("HTML escaped: {}", res);
+    #[tokio::test]
+    #[ignore]
+    async fn test_02_connect_to_somafm() {
+        let res = async {
+            use crate::spiders::session::Manager;
+            use fantoccini::Locator;
+
+            // Endpoint and TLS backend come from the config, see spiders::config
+            let c = WebDriverManager::new(&DriverConfig::default())
+                .try_connect()
+                .await
+                .expect("failed to connect to WebDriver");
+
+            c.goto("https://somafm.com/groovesalad/songhistory.html")
+                .await?;
+
+            let html = c.find(Locator::Css("#playinc")).await?.html(true).await?;
+
+            println!("HTML: {}", html);
+
+            c.close().await
+        };
 
-        assert!(true)
+        match res.await {
+            Ok(_) => {}
+            Err(e) => {
+                println!("ERROR: {:#?}", e);
+            }
+        };
     }
 
-    #[test]
-    fn test_04_scrape_html() {
-        let html_escaped_payload = r#"
+    #[tokio::test]
+    async fn test_03_escape_html() {
+        let html_payload = r#"
 <table width="100%" border="0">
-<tbody><tr>
-  <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td></tr><tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+<tbody>
+<tr>
+ <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td>
+ </tr>
+
+ <tr>
+ <td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td>
+ </tr>
 
 <!-- line 1 -->
-<tr><td>14:21:19  (Now) </td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Last%20Place%20To%20Hide&artist=Welder%20%26%20Seed&album=Chime&album=Chime" title="Search Amazon for Welder & Seed">Welder & Seed</a></td><td>Last Place To Hide</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Last%20Place%20To%20Hide&album=Chime&artist=Welder%20%26%20Seed" title="Search Amazon for Chime">Chime</a></td>
+<tr><td>14:21:19&nbsp; (Now) </td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Last%20Place%20To%20Hide&amp;artist=Welder%20%26%20Seed&amp;album=Chime&amp;album=Chime" title="Search Amazon for Welder &amp; Seed">Welder &amp; Seed</a></td><td>Last Place To Hide</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Last%20Place%20To%20Hide&amp;album=Chime&amp;artist=Welder%20%26%20Seed" title="Search Amazon for Chime">Chime</a></td>
 <td></td>
 </tr>
 
 <!-- line 2 -->
 <tr><td>14:17:01</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J&album=Cafe%20del%20Mar%2C%20Vol.%2019&album=Cafe%20del%20Mar%2C%20Vol.%2019" title="Search Amazon for Hazy J">Hazy J</a></td><td>Our Way</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&album=Cafe%20del%20Mar%2C%20Vol.%2019&artist=Hazy%20J" title="Search Amazon for Cafe del Mar, Vol. 19">Cafe del Mar, Vol. 19</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Our%20Way&amp;artist=Hazy%20J&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019" title="Search Amazon for Hazy J">Hazy J</a></td><td>Our Way</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Our%20Way&amp;album=Cafe%20del%20Mar%2C%20Vol.%2019&amp;artist=Hazy%20J" title="Search Amazon for Cafe del Mar, Vol. 19">Cafe del Mar, Vol. 19</a></td>
 <td></td>
 </tr>
 
 <!-- line 3 -->
 <tr><td>14:12:06</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Cowboy%20Hero&artist=Experiment&album=What%20Goes%20Up&album=What%20Goes%20Up" title="Search Amazon for Experiment">Experiment</a></td><td>Cowboy Hero</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Cowboy%20Hero&album=What%20Goes%20Up&artist=Experiment" title="Search Amazon for What Goes Up">What Goes Up</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Cowboy%20Hero&amp;artist=Experiment&amp;album=What%20Goes%20Up&amp;album=What%20Goes%20Up" title="Search Amazon for Experiment">Experiment</a></td><td>Cowboy Hero</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Cowboy%20Hero&amp;album=What%20Goes%20Up&amp;artist=Experiment" title="Search Amazon for What Goes Up">What Goes Up</a></td>
 <td></td>
 </tr>
 
@@ -322,13 +1217,13 @@ mod tests {
 
 <!-- line 5 -->
 <tr><td>14:07:32</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz&album=Magnifico%21&album=Magnifico%21" title="Search Amazon for Alex Cortiz">Alex Cortiz</a></td><td>Glamourgirl</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&album=Magnifico%21&artist=Alex%20Cortiz" title="Search Amazon for Magnifico!">Magnifico!</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Glamourgirl&amp;artist=Alex%20Cortiz&amp;album=Magnifico%21&amp;album=Magnifico%21" title="Search Amazon for Alex Cortiz">Alex Cortiz</a></td><td>Glamourgirl</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Glamourgirl&amp;album=Magnifico%21&amp;artist=Alex%20Cortiz" title="Search Amazon for Magnifico!">Magnifico!</a></td>
 <td></td>
 </tr>
 
 <!-- line 6 -->
 <tr><td>14:02:23</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&artist=Kiln&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D" title="Search Amazon for Kiln">Kiln</a></td><td>Ore Corymb [Bursting Rainbow C</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&artist=Kiln" title="Search Amazon for Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]">Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&amp;artist=Kiln&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D" title="Search Amazon for Kiln">Kiln</a></td><td>Ore Corymb [Bursting Rainbow C</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Ore%20Corymb%20%5BBursting%20Rainbow%20C&amp;album=Twinewheel%20%5BLost-Sides%20and%20Dusty-Gems%201994-2005%5D&amp;artist=Kiln" title="Search Amazon for Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]">Twinewheel [Lost-Sides and Dusty-Gems 1994-2005]</a></td>
 <td></td>
 </tr>
 
@@ -340,13 +1235,13 @@ mod tests {
 
 <!-- line 8 -->
 <tr><td>13:53:16</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Porta%20Mia%20Via&artist=Campa&album=Klassik%20Lounge%20-%20Werk%204&album=Klassik%20Lounge%20-%20Werk%204" title="Search Amazon for Campa">Campa</a></td><td>Porta Mia Via</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Porta%20Mia%20Via&album=Klassik%20Lounge%20-%20Werk%204&artist=Campa" title="Search Amazon for Klassik Lounge - Werk 4">Klassik Lounge - Werk 4</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Porta%20Mia%20Via&amp;artist=Campa&amp;album=Klassik%20Lounge%20-%20Werk%204&amp;album=Klassik%20Lounge%20-%20Werk%204" title="Search Amazon for Campa">Campa</a></td><td>Porta Mia Via</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Porta%20Mia%20Via&amp;album=Klassik%20Lounge%20-%20Werk%204&amp;artist=Campa" title="Search Amazon for Klassik Lounge - Werk 4">Klassik Lounge - Werk 4</a></td>
 <td></td>
 </tr>
 
 <!-- line 9 -->
 <tr><td>13:48:52</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Astro%20Radio&artist=Or-If-Is&album=This%20Is&album=This%20Is" title="Search Amazon for Or-If-Is">Or-If-Is</a></td><td>Astro Radio</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Astro%20Radio&album=This%20Is&artist=Or-If-Is" title="Search Amazon for This Is">This Is</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Astro%20Radio&amp;artist=Or-If-Is&amp;album=This%20Is&amp;album=This%20Is" title="Search Amazon for Or-If-Is">Or-If-Is</a></td><td>Astro Radio</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Astro%20Radio&amp;album=This%20Is&amp;artist=Or-If-Is" title="Search Amazon for This Is">This Is</a></td>
 <td></td>
 </tr>
 
@@ -358,31 +1253,31 @@ mod tests {
 
 <!-- line 11 -->
 <tr><td>13:38:14</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Even%20So&artist=Setsuna&album=Autumn%20Time%20Vol.%203&album=Autumn%20Time%20Vol.%203" title="Search Amazon for Setsuna">Setsuna</a></td><td>Even So</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Even%20So&album=Autumn%20Time%20Vol.%203&artist=Setsuna" title="Search Amazon for Autumn Time Vol. 3">Autumn Time Vol. 3</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Even%20So&amp;artist=Setsuna&amp;album=Autumn%20Time%20Vol.%203&amp;album=Autumn%20Time%20Vol.%203" title="Search Amazon for Setsuna">Setsuna</a></td><td>Even So</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Even%20So&amp;album=Autumn%20Time%20Vol.%203&amp;artist=Setsuna" title="Search Amazon for Autumn Time Vol. 3">Autumn Time Vol. 3</a></td>
 <td></td>
 </tr>
 
 <!-- line 12 -->
 <tr><td>13:32:54</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&artist=Yoko%20Kanno&album=Cowboy%20Bebop%20Remixes&album=Cowboy%20Bebop%20Remixes" title="Search Amazon for Yoko Kanno">Yoko Kanno</a></td><td>Forever Broke (Fila Brazillia Remix)</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&album=Cowboy%20Bebop%20Remixes&artist=Yoko%20Kanno" title="Search Amazon for Cowboy Bebop Remixes">Cowboy Bebop Remixes</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&amp;artist=Yoko%20Kanno&amp;album=Cowboy%20Bebop%20Remixes&amp;album=Cowboy%20Bebop%20Remixes" title="Search Amazon for Yoko Kanno">Yoko Kanno</a></td><td>Forever Broke (Fila Brazillia Remix)</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Forever%20Broke%20%28Fila%20Brazillia%20Remix%29&amp;album=Cowboy%20Bebop%20Remixes&amp;artist=Yoko%20Kanno" title="Search Amazon for Cowboy Bebop Remixes">Cowboy Bebop Remixes</a></td>
 <td></td>
 </tr>
 
 <!-- line 13 -->
 <tr><td>13:27:46</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&artist=Lounge%20Deluxe&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29" title="Search Amazon for Lounge Deluxe">Lounge Deluxe</a></td><td>This Is All I Ask (Oxygene Dow</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&artist=Lounge%20Deluxe" title="Search Amazon for Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)">Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&amp;artist=Lounge%20Deluxe&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29" title="Search Amazon for Lounge Deluxe">Lounge Deluxe</a></td><td>This Is All I Ask (Oxygene Dow</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=This%20Is%20All%20I%20Ask%20%28Oxygene%20Dow&amp;album=Cafe%20Dubai%2C%20a%20Trip%20Into%20Sunset%20Lounge%20%28The%20Best%20in%20Chill%20Out%20and%20Dessert%20Feelings%29&amp;artist=Lounge%20Deluxe" title="Search Amazon for Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)">Cafe Dubai, a Trip Into Sunset Lounge (The Best in Chill Out and Dessert Feelings)</a></td>
 <td></td>
 </tr>
 
 <!-- line 14 -->
 <tr><td>13:21:03</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Knuddelmaus&artist=Ulrich%20Schnauss&album=Far%20Away%20Trains%20Passing%20By&album=Far%20Away%20Trains%20Passing%20By" title="Search Amazon for Ulrich Schnauss">Ulrich Schnauss</a></td><td>Knuddelmaus</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Knuddelmaus&album=Far%20Away%20Trains%20Passing%20By&artist=Ulrich%20Schnauss" title="Search Amazon for Far Away Trains Passing By">Far Away Trains Passing By</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Knuddelmaus&amp;artist=Ulrich%20Schnauss&amp;album=Far%20Away%20Trains%20Passing%20By&amp;album=Far%20Away%20Trains%20Passing%20By" title="Search Amazon for Ulrich Schnauss">Ulrich Schnauss</a></td><td>Knuddelmaus</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Knuddelmaus&amp;album=Far%20Away%20Trains%20Passing%20By&amp;artist=Ulrich%20Schnauss" title="Search Amazon for Far Away Trains Passing By">Far Away Trains Passing By</a></td>
 <td></td>
 </tr>
 
 <!-- line 15 -->
 <tr><td>13:15:01</td>
-<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Sola%20Systim&artist=Underworld&album=Ansum&album=Ansum" title="Search Amazon for Underworld">Underworld</a></td><td>Sola Systim</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Sola%20Systim&album=Ansum&artist=Underworld" title="Search Amazon for Ansum">Ansum</a></td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Sola%20Systim&amp;artist=Underworld&amp;album=Ansum&amp;album=Ansum" title="Search Amazon for Underworld">Underworld</a></td><td>Sola Systim</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&amp;title=Sola%20Systim&amp;album=Ansum&amp;artist=Underworld" title="Search Amazon for Ansum">Ansum</a></td>
 <td></td>
 </tr>
 
@@ -408,13 +1303,27 @@ mod tests {
 
 <!-- line 20 -->
 <tr><td>13:01:23</td>
-<td><a target="_blank" href="https://www.discogs.com/artist/Sofa+Lofa" title="Go to Sofa Lofa’s site">Sofa Lofa</a></td><td>Magic Shopkeeper</td><td><a target="_blank" href="https://bathysphere.co.uk" title="More information for Bathesphere recordings 7"">Bathesphere recordings 7"</a></td>
+<td><a target="_blank" href="https://www.discogs.com/artist/Sofa+Lofa" title="Go to Sofa Lofa’s site">Sofa Lofa</a></td><td>Magic Shopkeeper</td><td><a target="_blank" href="https://bathysphere.co.uk" title="More information for Bathesphere recordings 7&quot;">Bathesphere recordings 7"</a></td>
 <td></td>
 </tr>
+
 <tr><td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td></tr>
+
 </tbody></table>
-      "#;
+      "#.to_string();
+
+        let res = html_escape::decode_html_entities(html_payload.as_str()).to_string();
+
+        println!("HTML escaped: {}", res);
+
+        assert!(!res.contains("&amp;"))
+    }
+
+    #[test]
+    fn test_04_scrape_html() {
+        let html_escaped_payload = SONG_HISTORY_HTML;
 
+        use anyhow::Context;
         use scraper::{ElementRef, Html, Selector};
 
         let res = || -> Result<(), anyhow::Error> {
@@ -451,7 +1360,7 @@ mod tests {
                     continue 'next_row;
                 }
 
-                let get_inner = |td: ElementRef| -> Result<String> {
+                let get_inner = |td: ElementRef| -> anyhow::Result<String> {
                     let res = td
                         .select(&a_selector)
                         .next()
@@ -474,25 +1383,534 @@ mod tests {
             Ok(())
         };
 
-        match res() {
-            Ok(_) => {}
-            Err(e) => {
-                println!("ERROR: {:#?}", e);
-            }
-        };
+        let res = res();
+        if let Err(e) = &res {
+            println!("ERROR: {:#?}", e);
+        }
 
-        assert!(true)
+        assert!(res.is_ok())
     }
 
     #[tokio::test]
     #[ignore]
     async fn test_05_store_scraped_result() {
-        let html = try_get_playlist().await.unwrap();
+        let fetcher = WebDriverFetcher::new(&DriverConfig::default());
+
+        let html = try_get_playlist(&fetcher, &Station::GROOVE_SALAD)
+            .await
+            .unwrap();
 
         let playlist = try_scrape_html(html.as_str()).unwrap();
 
         println!("Playlist Vec: {:#?}", playlist);
 
-        assert!(true)
+        assert!(!playlist.is_empty())
+    }
+
+    #[test]
+    fn test_06_parse_station() {
+        let res = "dronezone".parse::<Station>().unwrap();
+
+        assert_eq!(res, Station::DRONE_ZONE);
+        assert_eq!(res.slug(), "dronezone");
+        assert_eq!(
+            res.song_history_url(),
+            "https://somafm.com/dronezone/songhistory.html"
+        );
+        assert_eq!(Station::default(), Station::GROOVE_SALAD);
+
+        assert!("".parse::<Station>().is_err());
+        assert!("Groove Salad".parse::<Station>().is_err());
+        assert!("../groovesalad".parse::<Station>().is_err());
+    }
+
+    #[test]
+    fn test_07_scrape_station() {
+        let res = try_scrape_station(Station::LUSH, SONG_HISTORY_HTML, ScrapeMode::Strict).unwrap();
+
+        assert_eq!(res.station(), &Station::LUSH);
+        assert_eq!(res.playlist(), try_scrape_html(SONG_HISTORY_HTML).unwrap());
+        assert!(res.to_string().starts_with("# lush\n"));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_08_fetch_playlists() {
+        let stations = [Station::GROOVE_SALAD, Station::DRONE_ZONE, Station::LUSH];
+
+        let fetcher = Arc::new(WebDriverFetcher::new(&DriverConfig::default()));
+
+        let res = try_fetch_playlists(fetcher, &stations, ScrapeMode::Strict)
+            .await
+            .unwrap();
+
+        println!("Playlists: {:#?}", res);
+
+        assert_eq!(
+            res.iter().map(StationPlaylist::station).collect::<Vec<_>>(),
+            stations.iter().collect::<Vec<_>>()
+        )
+    }
+
+    const CHANNELS_JSON: &str = r#"{"channels":[
+{"id":"groovesalad","title":"Groove Salad","description":"A nicely chilled plate of ambient/downtempo beats and grooves.","dj":"Rusty Hodge","djmail":"rusty@somafm.com","genre":"ambient|electronica","image":"https://api.somafm.com/img/groovesalad120.png","largeimage":"https://api.somafm.com/logos/256/groovesalad256.png","xlimage":"https://api.somafm.com/logos/512/groovesalad512.png","twitter":"","updated":"1396144686","playlists":[{"url":"https://api.somafm.com/groovesalad.pls","format":"mp3","quality":"highest"},{"url":"https://api.somafm.com/groovesalad130.pls","format":"aac","quality":"highest"},{"url":"https://api.somafm.com/groovesalad64.pls","format":"aacp","quality":"high"}],"preroll":[],"listeners":"1873","lastPlaying":"Welder & Seed - Last Place To Hide"},
+{"id":"dronezone","title":"Drone Zone","description":"Served best chilled, safe with most medications. Atmospheric textures with minimal beats.","dj":"Rusty Hodge","djmail":"rusty@somafm.com","genre":"ambient|space","image":"https://api.somafm.com/img/dronezone120.jpg","largeimage":"https://api.somafm.com/logos/256/dronezone256.png","xlimage":"https://api.somafm.com/logos/512/dronezone512.png","twitter":"dronezone","updated":"1396144686","playlists":[{"url":"https://api.somafm.com/dronezone.pls","format":"mp3","quality":"highest"}],"preroll":[],"listeners":"1021","lastPlaying":"Stars Of The Lid - Dungtitled (In A Major)"},
+{"id":"secretagent","title":"Secret Agent","description":"The soundtrack for your stylish, mysterious, dangerous life. For Spies and PIs too!","dj":"Rusty Hodge","djmail":"rusty@somafm.com","genre":"lounge|eclectic","image":"https://api.somafm.com/img/secretagent120.jpg","largeimage":"https://api.somafm.com/logos/256/secretagent256.png","xlimage":"https://api.somafm.com/logos/512/secretagent512.png","twitter":"","updated":"1396144686","playlists":[],"preroll":[],"listeners":"","lastPlaying":"Thievery Corporation - The Richest Man In Babylon"}
+]}"#;
+
+    #[test]
+    fn test_09_parse_channels() {
+        let res = try_parse_channels(CHANNELS_JSON).unwrap();
+        println!("Channels: {:#?}", res);
+
+        assert_eq!(res.len(), 3);
+
+        assert_eq!(res[0].station(), &Station::GROOVE_SALAD);
+        assert_eq!(res[0].title(), "Groove Salad");
+        assert_eq!(res[0].genres(), ["ambient", "electronica"]);
+        assert_eq!(res[0].dj(), "Rusty Hodge");
+        assert_eq!(
+            res[0].description(),
+            "A nicely chilled plate of ambient/downtempo beats and grooves."
+        );
+        assert_eq!(res[0].listeners(), 1873);
+        assert_eq!(res[0].streams().len(), 3);
+        assert_eq!(
+            res[0].streams()[0].url(),
+            "https://api.somafm.com/groovesalad.pls"
+        );
+        assert_eq!(res[0].streams()[1].format(), "aac");
+        assert_eq!(res[0].streams()[2].quality(), "high");
+
+        assert_eq!(res[1].station(), &Station::DRONE_ZONE);
+
+        assert_eq!(res[2].station(), &Station::SECRET_AGENT);
+        assert_eq!(res[2].listeners(), 0);
+        assert!(res[2].streams().is_empty());
+    }
+
+    #[test]
+    fn test_10_parse_channels_invalid() {
+        assert!(try_parse_channels("<html></html>").is_err());
+        assert!(try_parse_channels(r#"{"channels":[{"id":"Not A Slug","title":"x"}]}"#).is_err());
+        assert!(try_parse_channels(
+            r#"{"channels":[{"id":"lush","title":"Lush","listeners":"many"}]}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_11_fetch_channels() {
+        let fetcher = HttpFetcher::try_new().unwrap();
+
+        let res = try_fetch_channels(&fetcher).await.unwrap();
+
+        println!("Channels: {:#?}", res);
+
+        assert!(res
+            .iter()
+            .any(|channel| channel.station() == &Station::GROOVE_SALAD))
+    }
+
+    #[tokio::test]
+    async fn test_12_fetch_playlists_from_stub() {
+        let fetcher = Arc::new(
+            StubFetcher::new()
+                .with_page(
+                    "https://somafm.com/groovesalad/songhistory.html",
+                    format!(
+                        r#"<html><body><div id="playinc">{}</div></body></html>"#,
+                        SONG_HISTORY_HTML
+                    )
+                    .as_str(),
+                )
+                .with_page(
+                    "https://somafm.com/lush/songhistory.html",
+                    format!(
+                        r#"<html><body><div id="playinc">{}</div></body></html>"#,
+                        SONG_HISTORY_HTML
+                    )
+                    .as_str(),
+                ),
+        );
+
+        let res = try_fetch_playlists(
+            fetcher.clone(),
+            &[Station::LUSH, Station::GROOVE_SALAD],
+            ScrapeMode::Strict,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].station(), &Station::LUSH);
+        assert_eq!(res[1].station(), &Station::GROOVE_SALAD);
+        assert_eq!(
+            res[0].playlist(),
+            try_scrape_html(SONG_HISTORY_HTML).unwrap()
+        );
+
+        assert!(
+            try_fetch_playlists(fetcher, &[Station::DRONE_ZONE], ScrapeMode::Strict)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_13_fetch_channels_from_stub() {
+        let fetcher =
+            StubFetcher::new().with_page("https://somafm.com/channels.json", CHANNELS_JSON);
+
+        let res = try_fetch_channels(&fetcher).await.unwrap();
+
+        assert_eq!(res.len(), 3)
+    }
+
+    const MIDNIGHT_HTML: &str = r#"
+<table width="100%" border="0">
+<tbody>
+<tr>
+ <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td>
+ </tr>
+
+ <tr>
+ <td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td>
+ </tr>
+
+<tr><td>00:02:10&nbsp; (Now) </td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J" title="Search Amazon for Hazy J">Hazy J</a></td><td>Our Way</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J" title="Search Amazon for Cafe del Mar, Vol. 19">Cafe del Mar, Vol. 19</a></td>
+<td></td>
+</tr>
+
+<tr><td>23:59:58</td>
+<td colspan="4"><span class="dim">Break / Station ID</span></td></tr>
+
+<tr><td>23:55:40</td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz" title="Search Amazon for Alex Cortiz">Alex Cortiz</a></td><td>Glamourgirl</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz" title="Search Amazon for Magnifico!">Magnifico!</a></td>
+<td></td>
+</tr>
+
+ <tr>
+ <td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td>
+ </tr>
+</tbody>
+</table>
+      "#;
+
+    #[test]
+    fn test_14_played_at() {
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 14, 25, 0).unwrap();
+
+        let res = try_scrape_html_at(SONG_HISTORY_HTML, reference).unwrap();
+
+        assert_eq!(
+            res[0].played_at(),
+            SOMAFM_TZ
+                .with_ymd_and_hms(2026, 10, 18, 14, 21, 19)
+                .unwrap()
+        );
+        assert!(res[0].is_now_playing());
+        assert!(res[1..].iter().all(|item| !item.is_now_playing()));
+        assert!(res
+            .windows(2)
+            .all(|pair| pair[0].played_at() >= pair[1].played_at()));
+    }
+
+    #[test]
+    fn test_15_played_at_rollover() {
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 3, 0).unwrap();
+
+        let res = try_scrape_html_at(MIDNIGHT_HTML, reference).unwrap();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(
+            res[0].played_at(),
+            SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 2, 10).unwrap()
+        );
+        assert_eq!(
+            res[1].played_at(),
+            SOMAFM_TZ
+                .with_ymd_and_hms(2026, 10, 17, 23, 55, 40)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_16_played_at_reference() {
+        // Page fetched just after midnight, but the newest row is still from yesterday
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 1, 0).unwrap();
+        let html = MIDNIGHT_HTML.replace("00:02:10", "23:59:59");
+
+        let res = try_scrape_html_at(html.as_str(), reference).unwrap();
+
+        assert_eq!(
+            res[0].played_at(),
+            SOMAFM_TZ
+                .with_ymd_and_hms(2026, 10, 17, 23, 59, 59)
+                .unwrap()
+        );
+        assert_eq!(
+            res[1].played_at(),
+            SOMAFM_TZ
+                .with_ymd_and_hms(2026, 10, 17, 23, 55, 40)
+                .unwrap()
+        );
+
+        // The newest row is a minute after the reference: skew, not yesterday
+        let res = try_scrape_html_at(MIDNIGHT_HTML, reference).unwrap();
+
+        assert_eq!(
+            res[0].played_at(),
+            SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 2, 10).unwrap()
+        );
+        assert_eq!(
+            res[1].played_at(),
+            SOMAFM_TZ
+                .with_ymd_and_hms(2026, 10, 17, 23, 55, 40)
+                .unwrap()
+        );
+
+        // A reference that lags just behind midnight still puts the row on the new day
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 17, 23, 59, 0).unwrap();
+        let res = try_scrape_html_at(MIDNIGHT_HTML, reference).unwrap();
+
+        assert_eq!(
+            res[0].played_at().date_naive(),
+            reference.date_naive().succ_opt().unwrap()
+        );
+
+        // More than an hour after the reference is yesterday after all
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 14, 0, 0).unwrap();
+        let html = MIDNIGHT_HTML.replace("00:02:10", "15:30:00");
+        let res = try_scrape_html_at(html.as_str(), reference).unwrap();
+
+        assert_eq!(
+            res[0].played_at(),
+            SOMAFM_TZ.with_ymd_and_hms(2026, 10, 17, 15, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_17_played_at_malformed() {
+        let html = MIDNIGHT_HTML.replace("23:55:40", "23:55");
+
+        match try_scrape_html(html.as_str()) {
+            Err(SpiderError::MalformedRow { index, html, .. }) => {
+                assert_eq!(index, 4);
+                assert!(html.contains("<td>23:55</td>"));
+            }
+            res => panic!("expected a malformed row, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_18_scrape_history_breaks() {
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 14, 25, 0).unwrap();
+
+        let res = try_scrape_history_at(SONG_HISTORY_HTML, reference).unwrap();
+
+        let breaks = res
+            .iter()
+            .filter(|entry| entry.is_break())
+            .collect::<Vec<_>>();
+        println!("Breaks: {:#?}", breaks);
+
+        assert_eq!(breaks.len(), 3);
+        assert_eq!(
+            breaks[0],
+            &HistoryEntry::Break {
+                played_at: SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 14, 12, 2).unwrap(),
+                duration: Some(Duration::seconds(4)),
+                label: "Break / Station ID".to_string(),
+            }
+        );
+
+        // The convenience filter is exactly the tracks, in the same order
+        assert_eq!(
+            res.iter()
+                .filter_map(HistoryEntry::as_track)
+                .cloned()
+                .collect::<Vec<_>>(),
+            try_scrape_html_at(SONG_HISTORY_HTML, reference).unwrap()
+        );
+        assert_eq!(res.len(), breaks.len() + 17);
+    }
+
+    #[test]
+    fn test_19_scrape_history_newest_break() {
+        let html = MIDNIGHT_HTML.replace(
+            r#"<tr><td>00:02:10&nbsp; (Now) </td>"#,
+            r#"<tr><td>00:02:10&nbsp; (Now) </td><td colspan="4"><span class="dim">Break / Station ID</span></td></tr><tr><td>00:01:00</td>"#,
+        );
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 3, 0).unwrap();
+
+        let res = try_scrape_history_at(html.as_str(), reference).unwrap();
+
+        assert!(matches!(res[0], HistoryEntry::Break { duration: None, .. }));
+        assert_eq!(
+            res[2],
+            HistoryEntry::Break {
+                played_at: SOMAFM_TZ
+                    .with_ymd_and_hms(2026, 10, 17, 23, 59, 58)
+                    .unwrap(),
+                duration: Some(Duration::seconds(62)),
+                label: "Break / Station ID".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_20_parse_link() {
+        let res =
+            Link::parse("/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J").unwrap();
+        assert_eq!(res.kind(), LinkKind::AmazonSearch);
+        assert_eq!(
+            res.url(),
+            "https://somafm.com/buy/multibuy.cgi?mode=amazon&title=Our%20Way&artist=Hazy%20J"
+        );
+
+        let res = Link::parse("https://banabila.bandcamp.com/album/voiznoiz").unwrap();
+        assert_eq!(res.kind(), LinkKind::Bandcamp);
+
+        let res = Link::parse("https://www.discogs.com/artist/Sofa+Lofa").unwrap();
+        assert_eq!(res.kind(), LinkKind::Discogs);
+
+        let res = Link::parse("https://iPool.info").unwrap();
+        assert_eq!(res.kind(), LinkKind::Homepage);
+        assert_eq!(res.url(), "https://ipool.info/");
+
+        // Lookalike hosts are just homepages
+        let res = Link::parse("https://notbandcamp.com/").unwrap();
+        assert_eq!(res.kind(), LinkKind::Homepage);
+
+        assert!(Link::parse("http://[not a url").is_none());
+    }
+
+    #[test]
+    fn test_21_scrape_links() {
+        let res = try_scrape_html(SONG_HISTORY_HTML).unwrap();
+
+        let find = |artist: &str| res.iter().find(|item| item.artist() == artist).unwrap();
+
+        let welder = find("Welder & Seed");
+        assert_eq!(
+            welder.artist_link().map(Link::kind),
+            Some(LinkKind::AmazonSearch)
+        );
+        assert!(welder
+            .album_link()
+            .unwrap()
+            .url()
+            .starts_with("https://somafm.com/buy/multibuy.cgi?mode=amazon&"));
+
+        let banabila = find("Banabila");
+        assert_eq!(
+            banabila.artist_link().map(Link::kind),
+            Some(LinkKind::Homepage)
+        );
+        assert_eq!(
+            banabila.album_link().map(Link::url),
+            Some("https://banabila.bandcamp.com/album/voiznoiz")
+        );
+        assert_eq!(
+            banabila.album_link().map(Link::kind),
+            Some(LinkKind::Bandcamp)
+        );
+
+        let sofa_lofa = find("Sofa Lofa");
+        assert_eq!(
+            sofa_lofa.artist_link().map(Link::kind),
+            Some(LinkKind::Discogs)
+        );
+
+        assert!(res.iter().all(|item| item.artist_link().is_some()));
+    }
+
+    #[test]
+    fn test_22_scrape_errors() {
+        let res = try_scrape_html("<div>No history right now</div>");
+        assert!(matches!(
+            res,
+            Err(SpiderError::MissingElement { ref selector, .. }) if selector == "table"
+        ));
+
+        let html = MIDNIGHT_HTML.replace(
+            r#"<a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz" title="Search Amazon for Alex Cortiz">Alex Cortiz</a>"#,
+            "Alex Cortiz",
+        );
+        match try_scrape_html(html.as_str()) {
+            Err(SpiderError::MalformedRow { index, reason, .. }) => {
+                assert_eq!(index, 4);
+                assert_eq!(reason, "no link in the artist column");
+            }
+            res => panic!("expected a malformed row, got {:?}", res),
+        }
+
+        assert!(matches!(
+            "Drone Zone".parse::<Station>(),
+            Err(SpiderError::Parse { .. })
+        ));
+        assert!(matches!(
+            try_parse_channels("<html></html>"),
+            Err(SpiderError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_23_scrape_lenient() {
+        let reference = SOMAFM_TZ.with_ymd_and_hms(2026, 10, 18, 0, 3, 0).unwrap();
+
+        // Newest track loses its time, the older one its artist link
+        let html = MIDNIGHT_HTML.replace("00:02:10", "00:02").replace(
+            r#"<a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=Glamourgirl&artist=Alex%20Cortiz" title="Search Amazon for Alex Cortiz">Alex Cortiz</a>"#,
+            "Alex Cortiz",
+        );
+
+        assert!(matches!(
+            try_scrape_history_report(html.as_str(), reference, ScrapeMode::Strict),
+            Err(SpiderError::MalformedRow { index: 2, .. })
+        ));
+
+        let res = try_scrape_history_report(html.as_str(), reference, ScrapeMode::Lenient).unwrap();
+        println!("Diagnostics: {:#?}", res.diagnostics());
+
+        // Only the break survives
+        assert_eq!(res.items().len(), 1);
+        assert!(res.items()[0].is_break());
+        assert_eq!(res.diagnostics().len(), 2);
+        assert_eq!(res.diagnostics()[0].index(), 2);
+        assert!(res.diagnostics()[0].html().contains("00:02"));
+        assert_eq!(
+            res.diagnostics()[1].to_string(),
+            "row 4: no link in the artist column"
+        );
+
+        // A clean page reports nothing, and gives the same rows as strict mode
+        let res = try_scrape_html_report(MIDNIGHT_HTML, reference, ScrapeMode::Lenient).unwrap();
+        assert!(res.diagnostics().is_empty());
+        assert_eq!(
+            res.into_items(),
+            try_scrape_html_at(MIDNIGHT_HTML, reference).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_24_scrape_station_lenient() {
+        let html = SONG_HISTORY_HTML.replacen("14:21:19", "later", 1);
+
+        assert!(try_scrape_station(Station::LUSH, html.as_str(), ScrapeMode::Strict).is_err());
+
+        let res = try_scrape_station(Station::LUSH, html.as_str(), ScrapeMode::Lenient).unwrap();
+
+        assert_eq!(res.playlist().len(), 16);
+        assert_eq!(res.diagnostics().len(), 1);
+
+        // Table-level problems still fail
+        assert!(matches!(
+            try_scrape_station(Station::LUSH, "<p></p>", ScrapeMode::Lenient),
+            Err(SpiderError::MissingElement { .. })
+        ));
     }
 }
@@ -51,7 +51,14 @@
     Learn more about the error here: https://github.com/Ixrec/rust-orphan-rules/issues/1
 */
 
+use crate::spiders::error::{selector, Result, SpiderError};
+use crate::spiders::fetch::Fetcher;
+use crate::spiders::normalize::NormalizedItem;
+use crate::spiders::somafm::PlaylistItem;
+use scraper::{ElementRef, Html};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TupleVec<T>(Vec<T>);
@@ -63,13 +70,319 @@ pub struct TupleHash<K, V>(HashMap<K, V>);
  * Datastructure
 ********************************************************************************************/
 
+// Serialized flat: the playlist item's fields, then `video`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct YPlaylistItem {
+    #[serde(flatten)]
+    playlist_item: PlaylistItem,
+    video: String,
+}
+
+impl std::fmt::Display for YPlaylistItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}  {}", self.playlist_item, self.video)
+    }
+}
+
+impl YPlaylistItem {
+    pub fn playlist_item(&self) -> &PlaylistItem {
+        &self.playlist_item
+    }
+
+    pub fn video(&self) -> &str {
+        &self.video
+    }
+}
 
+// One row of the search results page, in the order YouTube ranked it (rank 0 is the first hit)
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoCandidate {
+    rank: usize,
+    video_id: String,
+    title: String,
+    channel: String,
+    duration: Option<Duration>,
+    views: Option<u64>,
+}
+
+impl VideoCandidate {
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn video_id(&self) -> &str {
+        &self.video_id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn views(&self) -> Option<u64> {
+        self.views
+    }
+
+    pub fn video_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.video_id)
+    }
+}
+
+// Looks up the video URL for a single song; implemented by anything that can answer
+// "artist + song" -> "https://www.youtube.com/watch?v=..."
+pub trait Resolve {
+    fn try_resolve(&self, item: &PlaylistItem) -> Result<String>;
+}
 
 /********************************************************************************************
  * Methods
 ********************************************************************************************/
 
+// Same format as the README example: "Groove Matter + 97 Ways", from the normalized artist and
+// song, so "Groove Matter feat. Kiln + 97 Ways (Radio Edit)" searches the same
+pub fn search_terms(item: &PlaylistItem) -> String {
+    NormalizedItem::from(item).search_key()
+}
+
+// https://www.youtube.com/results?search_query=Groove+Matter+%2B+97+Ways
+pub fn search_url(artist: &str, song: &str) -> String {
+    let query = url::form_urlencoded::byte_serialize(format!("{} + {}", artist, song).as_bytes())
+        .collect::<String>();
+
+    format!("https://www.youtube.com/results?search_query={}", query)
+}
+
+// Results are rendered client side, so this needs a fetcher that runs a browser
+pub async fn try_get_search_results(
+    fetcher: &dyn Fetcher,
+    artist: &str,
+    song: &str,
+) -> Result<String> {
+    fetcher
+        .try_fetch_element(
+            search_url(artist, song).as_str(),
+            "ytd-item-section-renderer",
+        )
+        .await
+}
+
+pub fn try_scrape_search_html(html_payload: &str) -> Result<Vec<VideoCandidate>> {
+    let fragment = Html::parse_fragment(html_payload);
+
+    let video_selector = selector("ytd-video-renderer")?;
+    let title_selector = selector("a#video-title")?;
+    let channel_selector = selector("ytd-channel-name a")?;
+    let duration_selector = selector("ytd-thumbnail-overlay-time-status-renderer #text")?;
+    let metadata_selector = selector("#metadata-line span")?;
+
+    let get_text = |el: ElementRef| -> String {
+        html_escape::decode_html_entities(el.text().collect::<String>().trim()).to_string()
+    };
+
+    let res = fragment
+        .select(&video_selector)
+        .enumerate()
+        .map(|(rank, video)| {
+            let malformed =
+                |reason: String| SpiderError::malformed_row(rank, reason, video.html().as_str());
+
+            let link = video
+                .select(&title_selector)
+                .next()
+                .ok_or_else(|| malformed("no video title link".to_string()))?;
+
+            let href = link
+                .value()
+                .attr("href")
+                .ok_or_else(|| malformed("no href on the video title link".to_string()))?;
+
+            let video_id = parse_video_id(href)
+                .ok_or_else(|| malformed(format!("not a video link: {}", href)))?;
+
+            let title = match link.value().attr("title") {
+                Some(title) => html_escape::decode_html_entities(title).to_string(),
+                None => get_text(link),
+            };
+
+            let channel = video
+                .select(&channel_selector)
+                .next()
+                .map(get_text)
+                .unwrap_or_default();
+
+            let duration = video
+                .select(&duration_selector)
+                .next()
+                .and_then(|el| parse_duration(&get_text(el)));
+
+            let views = video
+                .select(&metadata_selector)
+                .map(get_text)
+                .find_map(|text| parse_views(&text));
+
+            Ok(VideoCandidate {
+                rank,
+                video_id,
+                title,
+                channel,
+                duration,
+                views,
+            })
+        })
+        .collect::<Result<Vec<VideoCandidate>>>()?;
+
+    Ok(res)
+}
+
+pub async fn try_search_videos(
+    fetcher: &dyn Fetcher,
+    artist: &str,
+    song: &str,
+) -> Result<Vec<VideoCandidate>> {
+    let html = try_get_search_results(fetcher, artist, song).await?;
+
+    try_scrape_search_html(html.as_str())
+}
+
+// Search every song in the playlist once. The fetcher's rate limiter spaces the searches out so
+// YouTube doesn't block us
+pub async fn try_search_playlist(
+    fetcher: &dyn Fetcher,
+    playlist: &[PlaylistItem],
+) -> Result<TupleHash<String, Vec<VideoCandidate>>> {
+    let mut res = HashMap::<String, Vec<VideoCandidate>>::new();
+
+    for item in playlist {
+        let normalized = NormalizedItem::from(item);
+        let key = normalized.search_key();
+
+        if res.contains_key(&key) {
+            continue;
+        }
+
+        let candidates = try_search_videos(fetcher, normalized.artist(), normalized.song()).await?;
+
+        res.insert(key, candidates);
+    }
+
+    Ok(TupleHash(res))
+}
+
+// A video id, or a watch URL to take the id from
+pub fn video_id(text: &str) -> Option<String> {
+    let text = text.trim();
+    let is_id = text.len() == 11
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match is_id {
+        true => Some(text.to_string()),
+        false => parse_video_id(text),
+    }
+}
+
+// "/watch?v=sjqLVcPd18E&pp=..." -> "sjqLVcPd18E"
+fn parse_video_id(href: &str) -> Option<String> {
+    let (_, query) = href.split_once("/watch?")?;
+
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "v")
+        .map(|(_, val)| val.to_string())
+        .filter(|id| !id.is_empty())
+}
+
+// "3:45" or "1:02:03"
+fn parse_duration(text: &str) -> Option<Duration> {
+    let secs = text.trim().split(':').try_fold(0u64, |accum, part| {
+        Some(accum * 60 + part.parse::<u64>().ok()?)
+    })?;
+
+    Some(Duration::from_secs(secs))
+}
+
+// "1,234 views", "1.2M views", "No views"
+fn parse_views(text: &str) -> Option<u64> {
+    let count = text.trim().strip_suffix("views")?.trim();
+
+    if count.eq_ignore_ascii_case("no") {
+        return Some(0);
+    }
+
+    let (number, multiplier) = match count.chars().last()? {
+        'K' => (&count[..count.len() - 1], 1_000.0),
+        'M' => (&count[..count.len() - 1], 1_000_000.0),
+        'B' => (&count[..count.len() - 1], 1_000_000_000.0),
+        _ => (count, 1.0),
+    };
+
+    let number = number.replace(',', "").parse::<f64>().ok()?;
+
+    Some((number * multiplier).round() as u64)
+}
+
+// Resolve from a lookup table keyed by `search_terms()`
+impl Resolve for HashMap<String, String> {
+    fn try_resolve(&self, item: &PlaylistItem) -> Result<String> {
+        let key = search_terms(item);
+
+        self.get(&key)
+            .cloned()
+            .ok_or(SpiderError::Unresolved { query: key })
+    }
+}
+
+impl<'a, R: Resolve> TryFrom<(Vec<PlaylistItem>, &'a R)> for TupleVec<YPlaylistItem> {
+    type Error = SpiderError;
+
+    fn try_from((playlist, resolver): (Vec<PlaylistItem>, &'a R)) -> Result<Self, Self::Error> {
+        let converted = playlist
+            .into_iter()
+            .map(|playlist_item| {
+                let video = resolver.try_resolve(&playlist_item)?;
+
+                Ok(YPlaylistItem {
+                    playlist_item,
+                    video,
+                })
+            })
+            .collect::<Result<Vec<YPlaylistItem>, Self::Error>>()?;
 
+        Ok(TupleVec(converted))
+    }
+}
+
+impl<T> TupleVec<T> {
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for TupleHash<K, V> {
+    fn from(inner: HashMap<K, V>) -> Self {
+        TupleHash(inner)
+    }
+}
+
+impl<K, V> TupleHash<K, V> {
+    pub fn into_inner(self) -> HashMap<K, V> {
+        self.0
+    }
+}
+
+impl TupleHash<String, Vec<VideoCandidate>> {
+    // What the search for this song found, in YouTube's order; empty if it wasn't searched
+    pub fn candidates(&self, item: &PlaylistItem) -> &[VideoCandidate] {
+        self.0.get(&search_terms(item)).map_or(&[], Vec::as_slice)
+    }
+}
 
 
 /********************************************************************************************
@@ -80,12 +393,188 @@ pub struct TupleHash<K, V>(HashMap<K, V>);
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spiders::config::DriverConfig;
+    use crate::spiders::fetch::{StubFetcher, WebDriverFetcher};
+    use crate::spiders::score::{BestMatch, MatchScorer};
+    use crate::spiders::somafm::try_scrape_html;
+
+    impl TryFrom<HashMap<&str, i32>> for TupleVec<i32> {
+        type Error = anyhow::Error;
+
+        fn try_from(inp: HashMap<&str, i32>) -> Result<Self, Self::Error> {
+            let converted = inp
+                .values()
+                .map(|val| {
+                    let res = *val;
+                    Ok(res)
+                })
+                .collect::<Result<Vec<i32>, Self::Error>>()?;
+
+            let res = TupleVec(converted);
+
+            Ok(res)
+        }
+    }
+
+    impl TryFrom<HashMap<&str, i32>> for TupleHash<String, u32> {
+        type Error = anyhow::Error;
+
+        fn try_from(inp: HashMap<&str, i32>) -> Result<Self, Self::Error> {
+            let converted =
+                inp.iter()
+                    .fold(HashMap::<String, u32>::new(), |mut accum, (key, val)| {
+                        let k_ = key.to_string();
+                        let v_ = val.to_owned() as u32;
+                        accum.insert(k_, v_);
+                        accum
+                    });
+
+            let res = TupleHash(converted);
+
+            Ok(res)
+        }
+    }
+
+    const PLAYLIST_HTML: &str = r#"
+<table width="100%" border="0">
+<tbody>
+<tr>
+ <td width="15%" class="boldblue">Played At</td><td width="25%" class="boldblue">Artist</td><td width="25%" class="boldblue">Song</td><td width="30%" class="boldblue">Album</td><td width="5%" class="boldblue"></td>
+ </tr>
+
+ <tr>
+ <td colspan="5"><img src="/img3/red.gif" height="1" width="100%" alt=""></td>
+ </tr>
+
+<tr><td>20:59:36</td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=5th%20%26%20Avenida&artist=Afterlife" title="Search Amazon for Afterlife">Afterlife</a></td><td>5th &amp; Avenida</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=5th%20%26%20Avenida&artist=Afterlife" title="Search Amazon for Cafe Del Mar Vol. 4">Cafe Del Mar Vol. 4</a></td>
+<td></td>
+</tr>
+
+<tr><td>20:55:01</td>
+<td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=97%20Ways&artist=Groove%20Matter" title="Search Amazon for Groove Matter">Groove Matter</a></td><td>97 Ways</td><td><a target="_blank" href="/buy/multibuy.cgi?mode=amazon&title=97%20Ways&artist=Groove%20Matter" title="Search Amazon for Groove Matter">Groove Matter</a></td>
+<td></td>
+</tr>
+</tbody>
+</table>
+      "#;
+
+    const SEARCH_HTML: &str = r#"
+<div id="contents" class="style-scope ytd-item-section-renderer">
+<ytd-video-renderer class="style-scope ytd-item-section-renderer">
+  <div id="dismissible" class="style-scope ytd-video-renderer">
+    <ytd-thumbnail class="style-scope ytd-video-renderer">
+      <a id="thumbnail" class="yt-simple-endpoint inline-block style-scope ytd-thumbnail" href="/watch?v=8HY3yD0sA8Y">
+        <div id="overlays" class="style-scope ytd-thumbnail">
+          <ytd-thumbnail-overlay-time-status-renderer class="style-scope ytd-thumbnail" overlay-style="DEFAULT">
+            <span id="text" class="style-scope ytd-thumbnail-overlay-time-status-renderer" aria-label="6 minutes, 12 seconds">
+              6:12
+            </span>
+          </ytd-thumbnail-overlay-time-status-renderer>
+        </div>
+      </a>
+    </ytd-thumbnail>
+    <div class="text-wrapper style-scope ytd-video-renderer">
+      <div id="meta" class="style-scope ytd-video-renderer">
+        <div id="title-wrapper" class="style-scope ytd-video-renderer">
+          <h3 class="title-and-badge style-scope ytd-video-renderer">
+            <a id="video-title" class="yt-simple-endpoint style-scope ytd-video-renderer" title="Groove Matter - 97 Ways" href="/watch?v=8HY3yD0sA8Y&amp;pp=sAQA">
+              <yt-formatted-string class="style-scope ytd-video-renderer">Groove Matter - 97 Ways</yt-formatted-string>
+            </a>
+          </h3>
+        </div>
+        <ytd-video-meta-block class="style-scope ytd-video-renderer byline-separated">
+          <div id="metadata" class="style-scope ytd-video-meta-block">
+            <div id="metadata-line" class="style-scope ytd-video-meta-block">
+              <span class="inline-metadata-item style-scope ytd-video-meta-block">48K views</span>
+              <span class="inline-metadata-item style-scope ytd-video-meta-block">9 years ago</span>
+            </div>
+          </div>
+        </ytd-video-meta-block>
+      </div>
+      <div id="channel-info" class="style-scope ytd-video-renderer">
+        <ytd-channel-name id="channel-name" class="long-byline style-scope ytd-video-renderer">
+          <div id="container" class="style-scope ytd-channel-name">
+            <div id="text-container" class="style-scope ytd-channel-name">
+              <yt-formatted-string id="text" class="style-scope ytd-channel-name complex-string"><a class="yt-simple-endpoint style-scope yt-formatted-string" href="/@groovematter-topic">Groove Matter - Topic</a></yt-formatted-string>
+            </div>
+          </div>
+        </ytd-channel-name>
+      </div>
+    </div>
+  </div>
+</ytd-video-renderer>
+<ytd-video-renderer class="style-scope ytd-item-section-renderer">
+  <div id="dismissible" class="style-scope ytd-video-renderer">
+    <ytd-thumbnail class="style-scope ytd-video-renderer">
+      <a id="thumbnail" class="yt-simple-endpoint inline-block style-scope ytd-thumbnail" href="/watch?v=Qm4bXo2vL0c">
+        <div id="overlays" class="style-scope ytd-thumbnail">
+          <ytd-thumbnail-overlay-time-status-renderer class="style-scope ytd-thumbnail" overlay-style="DEFAULT">
+            <span id="text" class="style-scope ytd-thumbnail-overlay-time-status-renderer">
+              1:02:03
+            </span>
+          </ytd-thumbnail-overlay-time-status-renderer>
+        </div>
+      </a>
+    </ytd-thumbnail>
+    <div class="text-wrapper style-scope ytd-video-renderer">
+      <div id="meta" class="style-scope ytd-video-renderer">
+        <div id="title-wrapper" class="style-scope ytd-video-renderer">
+          <h3 class="title-and-badge style-scope ytd-video-renderer">
+            <a id="video-title" class="yt-simple-endpoint style-scope ytd-video-renderer" title="Groove Salad Mix &amp; Chill - 1 Hour" href="/watch?v=Qm4bXo2vL0c">
+              <yt-formatted-string class="style-scope ytd-video-renderer">Groove Salad Mix &amp; Chill - 1 Hour</yt-formatted-string>
+            </a>
+          </h3>
+        </div>
+        <ytd-video-meta-block class="style-scope ytd-video-renderer byline-separated">
+          <div id="metadata" class="style-scope ytd-video-meta-block">
+            <div id="metadata-line" class="style-scope ytd-video-meta-block">
+              <span class="inline-metadata-item style-scope ytd-video-meta-block">1,234,567 views</span>
+              <span class="inline-metadata-item style-scope ytd-video-meta-block">2 years ago</span>
+            </div>
+          </div>
+        </ytd-video-meta-block>
+      </div>
+      <div id="channel-info" class="style-scope ytd-video-renderer">
+        <ytd-channel-name id="channel-name" class="long-byline style-scope ytd-video-renderer">
+          <div id="container" class="style-scope ytd-channel-name">
+            <div id="text-container" class="style-scope ytd-channel-name">
+              <yt-formatted-string id="text" class="style-scope ytd-channel-name complex-string"><a class="yt-simple-endpoint style-scope yt-formatted-string" href="/@chillmixes">Chill Mixes</a></yt-formatted-string>
+            </div>
+          </div>
+        </ytd-channel-name>
+      </div>
+    </div>
+  </div>
+</ytd-video-renderer>
+<ytd-video-renderer class="style-scope ytd-item-section-renderer">
+  <div id="dismissible" class="style-scope ytd-video-renderer">
+    <div class="text-wrapper style-scope ytd-video-renderer">
+      <div id="meta" class="style-scope ytd-video-renderer">
+        <div id="title-wrapper" class="style-scope ytd-video-renderer">
+          <h3 class="title-and-badge style-scope ytd-video-renderer">
+            <a id="video-title" class="yt-simple-endpoint style-scope ytd-video-renderer" title="97 Ways (live)" href="/watch?v=zzTopLive01">
+              <yt-formatted-string class="style-scope ytd-video-renderer">97 Ways (live)</yt-formatted-string>
+            </a>
+          </h3>
+        </div>
+        <ytd-video-meta-block class="style-scope ytd-video-renderer byline-separated">
+          <div id="metadata" class="style-scope ytd-video-meta-block">
+            <div id="metadata-line" class="style-scope ytd-video-meta-block">
+              <span class="inline-metadata-item style-scope ytd-video-meta-block">No views</span>
+            </div>
+          </div>
+        </ytd-video-meta-block>
+      </div>
+    </div>
+  </div>
+</ytd-video-renderer>
+</div>
+      "#;
 
     #[test]
     fn test_01() {
         println!("Hello youtube test!");
-
-        assert!(true)
     }
 
     #[test]
@@ -103,28 +592,10 @@ mod tests {
             ("h", 8),
         ]);
 
-        impl TryFrom<HashMap<&str, i32>> for TupleVec<i32> {
-            type Error = anyhow::Error;
-
-            fn try_from(inp: HashMap<&str, i32>) -> Result<Self, Self::Error> {
-                let converted = inp
-                    .iter()
-                    .map(|(key, val)| {
-                        let res = val.clone();
-                        Ok(res)
-                    })
-                    .collect::<Result<Vec<i32>, Self::Error>>()?;
-
-                let res = TupleVec(converted);
-
-                Ok(res)
-            }
-        }
-
         let res = TupleVec::<i32>::try_from(input).unwrap();
         println!("Result: {:#?}", res);
 
-        assert!(true)
+        assert_eq!(res.0.len(), 8)
     }
 
     #[test]
@@ -142,28 +613,166 @@ mod tests {
             ("h", 8),
         ]);
 
-        impl TryFrom<HashMap<&str, i32>> for TupleHash<String, u32> {
-            type Error = anyhow::Error;
+        let res = TupleHash::<String, u32>::try_from(input).unwrap();
+        println!("Result: {:#?}", res);
 
-            fn try_from(inp: HashMap<&str, i32>) -> Result<Self, Self::Error> {
-                let converted =
-                    inp.iter()
-                        .fold(HashMap::<String, u32>::new(), |mut accum, (key, val)| {
-                            let k_ = key.to_string();
-                            let v_ = val.to_owned() as u32;
-                            accum.insert(k_, v_);
-                            accum
-                        });
+        assert_eq!(res.0.get("h"), Some(&8))
+    }
 
-                let res = TupleHash(converted);
+    #[test]
+    fn test_04_try_from_playlist() {
+        let playlist = try_scrape_html(PLAYLIST_HTML).unwrap();
+
+        let videos = HashMap::<String, String>::from([
+            (
+                "Afterlife + 5th & Avenida".to_string(),
+                "https://www.youtube.com/watch?v=sjqLVcPd18E".to_string(),
+            ),
+            (
+                "Groove Matter + 97 Ways".to_string(),
+                "https://www.youtube.com/watch?v=8HY3yD0sA8Y".to_string(),
+            ),
+        ]);
 
-                Ok(res)
-            }
-        }
+        let res = TupleVec::<YPlaylistItem>::try_from((playlist.clone(), &videos)).unwrap();
+        println!("Result: {:#?}", res);
 
-        let res = TupleHash::<String, u32>::try_from(input).unwrap();
+        let res = res.into_inner();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].playlist_item(), &playlist[0]);
+        assert_eq!(
+            res[0].video(),
+            "https://www.youtube.com/watch?v=sjqLVcPd18E"
+        );
+        assert_eq!(
+            res[1].video(),
+            "https://www.youtube.com/watch?v=8HY3yD0sA8Y"
+        );
+    }
+
+    #[test]
+    fn test_05_try_from_playlist_unresolved() {
+        let playlist = try_scrape_html(PLAYLIST_HTML).unwrap();
+
+        let videos = HashMap::<String, String>::from([(
+            "Afterlife + 5th & Avenida".to_string(),
+            "https://www.youtube.com/watch?v=sjqLVcPd18E".to_string(),
+        )]);
+
+        let res = TupleVec::<YPlaylistItem>::try_from((playlist, &videos));
+
+        assert!(matches!(
+            res,
+            Err(SpiderError::Unresolved { ref query }) if query == "Groove Matter + 97 Ways"
+        ))
+    }
+
+    #[test]
+    fn test_06_search_url() {
+        let res = search_url("Groove Matter", "97 Ways");
+
+        assert_eq!(
+            res,
+            "https://www.youtube.com/results?search_query=Groove+Matter+%2B+97+Ways"
+        )
+    }
+
+    #[test]
+    fn test_07_scrape_search_html() {
+        let res = try_scrape_search_html(SEARCH_HTML).unwrap();
         println!("Result: {:#?}", res);
 
-        assert!(true)
+        assert_eq!(res.len(), 3);
+
+        assert_eq!(res[0].rank(), 0);
+        assert_eq!(res[0].video_id(), "8HY3yD0sA8Y");
+        assert_eq!(res[0].title(), "Groove Matter - 97 Ways");
+        assert_eq!(res[0].channel(), "Groove Matter - Topic");
+        assert_eq!(res[0].duration(), Some(Duration::from_secs(372)));
+        assert_eq!(res[0].views(), Some(48_000));
+        assert_eq!(
+            res[0].video_url(),
+            "https://www.youtube.com/watch?v=8HY3yD0sA8Y"
+        );
+
+        assert_eq!(res[1].rank(), 1);
+        assert_eq!(res[1].title(), "Groove Salad Mix & Chill - 1 Hour");
+        assert_eq!(res[1].duration(), Some(Duration::from_secs(3723)));
+        assert_eq!(res[1].views(), Some(1_234_567));
+
+        assert_eq!(res[2].rank(), 2);
+        assert_eq!(res[2].channel(), "");
+        assert_eq!(res[2].duration(), None);
+        assert_eq!(res[2].views(), Some(0));
+    }
+
+    #[test]
+    fn test_08_scrape_search_html_no_results() {
+        let res = try_scrape_search_html(r#"<div id="contents"></div>"#).unwrap();
+
+        assert!(res.is_empty())
+    }
+
+    #[test]
+    fn test_09_resolve_from_search_results() {
+        let playlist = try_scrape_html(PLAYLIST_HTML).unwrap();
+
+        let results = TupleHash(HashMap::<String, Vec<VideoCandidate>>::from([
+            ("Afterlife + 5th & Avenida".to_string(), vec![]),
+            (
+                "Groove Matter + 97 Ways".to_string(),
+                try_scrape_search_html(SEARCH_HTML).unwrap(),
+            ),
+        ]));
+
+        let scorer = MatchScorer::default();
+        let resolver = BestMatch::new(&scorer, &results);
+
+        assert!(resolver.try_resolve(&playlist[0]).is_err());
+        assert_eq!(
+            resolver.try_resolve(&playlist[1]).unwrap(),
+            "https://www.youtube.com/watch?v=8HY3yD0sA8Y"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_10_search_videos() {
+        let fetcher = WebDriverFetcher::new(&DriverConfig::default());
+
+        let res = try_search_videos(&fetcher, "Groove Matter", "97 Ways")
+            .await
+            .unwrap();
+
+        println!("Candidates: {:#?}", res);
+
+        assert!(!res.is_empty())
+    }
+
+    #[tokio::test]
+    async fn test_11_search_playlist_from_stub() {
+        let playlist = try_scrape_html(PLAYLIST_HTML).unwrap();
+
+        let results = format!(
+            "<ytd-item-section-renderer>{}</ytd-item-section-renderer>",
+            SEARCH_HTML
+        );
+        let fetcher = StubFetcher::new()
+            .with_page(
+                search_url("Afterlife", "5th & Avenida").as_str(),
+                results.as_str(),
+            )
+            .with_page(
+                search_url("Groove Matter", "97 Ways").as_str(),
+                results.as_str(),
+            );
+
+        let res = try_search_playlist(&fetcher, &playlist)
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res["Groove Matter + 97 Ways"].len(), 3);
     }
 }
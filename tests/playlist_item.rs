@@ -0,0 +1,97 @@
+use chrono::TimeZone;
+use rust_demo::spiders::error::SpiderError;
+use rust_demo::spiders::somafm::{Link, LinkKind, PlaylistItem, SOMAFM_TZ};
+use rust_demo::spiders::youtube::{self, Resolve, TupleVec, YPlaylistItem};
+use std::collections::HashMap;
+
+fn played_at() -> chrono::DateTime<chrono_tz::Tz> {
+    SOMAFM_TZ
+        .with_ymd_and_hms(2026, 10, 18, 14, 21, 19)
+        .unwrap()
+}
+
+#[test]
+fn test_01_new() {
+    let res = PlaylistItem::try_new(
+        played_at(),
+        "Afterlife",
+        "Dub, Strings and a Drum",
+        "Simply",
+    )
+    .unwrap();
+
+    assert_eq!(res.played_at(), played_at());
+    assert_eq!(res.artist(), "Afterlife");
+    assert_eq!(res.song(), "Dub, Strings and a Drum");
+    assert_eq!(res.album(), "Simply");
+    assert!(!res.is_now_playing());
+    assert!(res.artist_link().is_none());
+    assert!(res.album_link().is_none());
+}
+
+#[test]
+fn test_02_new_rejects_blank() {
+    assert!(matches!(
+        PlaylistItem::try_new(played_at(), "", "Sun Dance", "Groovology"),
+        Err(SpiderError::Parse { ref reason, .. }) if reason == "artist is empty"
+    ));
+    assert!(matches!(
+        PlaylistItem::try_new(played_at(), "Groove Matter", "  ", "Groovology"),
+        Err(SpiderError::Parse { ref reason, .. }) if reason == "song is empty"
+    ));
+
+    // Promo tracks often have no album
+    assert!(PlaylistItem::try_new(played_at(), "Groove Matter", "Sun Dance", "").is_ok());
+}
+
+#[test]
+fn test_03_builder() {
+    let artist_link = Link::parse("https://www.discogs.com/artist/Afterlife").unwrap();
+    let album_link = Link::parse("/buy/multibuy.cgi?mode=amazon&title=Simply").unwrap();
+
+    let res = PlaylistItem::builder(played_at(), "Afterlife", "Dub, Strings and a Drum")
+        .album("Simply")
+        .now_playing(true)
+        .artist_link(artist_link.clone())
+        .album_link(album_link)
+        .try_build()
+        .unwrap();
+
+    assert_eq!(res.album(), "Simply");
+    assert!(res.is_now_playing());
+    assert_eq!(res.artist_link(), Some(&artist_link));
+    assert_eq!(res.album_link().unwrap().kind(), LinkKind::AmazonSearch);
+    assert_eq!(
+        res.to_string(),
+        "2026-10-18 14:21:19  Afterlife - Dub, Strings and a Drum (Simply) (Now)"
+    );
+
+    assert!(PlaylistItem::builder(played_at(), "Afterlife", "")
+        .album("Simply")
+        .try_build()
+        .is_err());
+}
+
+#[test]
+fn test_04_resolve() {
+    let item = PlaylistItem::try_new(played_at(), "Afterlife", "Sun Dance", "Simply").unwrap();
+    let resolver = HashMap::from([(
+        youtube::search_terms(&item),
+        "https://www.youtube.com/watch?v=Qp6pS3cvCKk".to_string(),
+    )]);
+
+    assert_eq!(
+        resolver.try_resolve(&item).unwrap(),
+        "https://www.youtube.com/watch?v=Qp6pS3cvCKk"
+    );
+
+    let res = TupleVec::<YPlaylistItem>::try_from((vec![item.clone()], &resolver))
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(res[0].playlist_item(), &item);
+    assert_eq!(
+        res[0].video(),
+        "https://www.youtube.com/watch?v=Qp6pS3cvCKk"
+    );
+}